@@ -73,12 +73,12 @@ fn main() -> Result<(), TopLevelError> {
     for (key, mut val) in mapping.drain() {
         final_allergens.push((key, val.drain().next().unwrap()));
     }
-    final_allergens.sort_by(|(k1, _), (k2, _)| k1.cmp(k2));
+    final_allergens.sort_by_key(|(k1, _)| *k1);
     let foods: Vec<&str> = final_allergens.iter().map(|(_, v)| *v).collect();
     let mut result = String::new();
 
     for food in foods.iter() {
-        result.push_str(*food);
+        result.push_str(food);
         result.push(',');
     }
     result.pop().unwrap();
@@ -1,50 +1,82 @@
-use advent2020::errors::{BitmaskCommandParseError, MaskParseError, TopLevelError};
-use std::collections::BTreeMap;
+use advent2020::errors::{BitmaskCommandParseError, ComputerError, MaskParseError, TopLevelError};
+use std::collections::{BTreeMap, BTreeSet};
 use std::env;
+use std::fmt::Write;
 use std::fs;
 use std::str::FromStr;
 
-struct Mask {
-    or_part: u64,
-    and_part: u64,
+const DEFAULT_WIDTH: u32 = 36;
+// Dumps list every address, so refuse once floating writes cover more
+// than this many.
+const DUMP_LIMIT: u128 = 1 << 20;
+
+fn word_mask(width: u32) -> u128 {
+    if width >= 128 {
+        u128::MAX
+    } else {
+        (1 << width) - 1
+    }
 }
 
-impl FromStr for Mask {
-    type Err = MaskParseError;
+/// Parses a mask into its `1` bits, its `X` bits, and how many bits it
+/// covers. Masks are either binary (`10X1`) or hex with a `0x` prefix, in
+/// which case an `X` digit means four `X` bits.
+fn parse_mask(s: &str) -> Result<(u128, u128, u32), MaskParseError> {
+    let (digits, radix_bits) = match s.strip_prefix("0x") {
+        Some(rest) => (rest, 4),
+        None => (s, 1),
+    };
+    let bits = digits.len() * radix_bits as usize;
+
+    if digits.is_empty() {
+        return Err(MaskParseError::Empty);
+    }
+    if bits > 128 {
+        return Err(MaskParseError::TooLong(bits));
+    }
 
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        if s.len() != 36 {
-            return Err(MaskParseError::WrongLength(s.len()));
+    let mut ones: u128 = 0;
+    let mut floating: u128 = 0;
+
+    for char in digits.chars() {
+        ones <<= radix_bits;
+        floating <<= radix_bits;
+        match char {
+            'X' => floating |= (1 << radix_bits) - 1,
+            _ => match char.to_digit(1 << radix_bits) {
+                Some(digit) => ones |= digit as u128,
+                None => return Err(MaskParseError::UnexpectedCharacter(char)),
+            },
         }
+    }
 
-        let mut or_part = 0;
-        let mut and_part = 0;
+    Ok((ones, floating, bits as u32))
+}
 
-        for char in s.chars() {
-            or_part <<= 1;
-            and_part <<= 1;
-            match char {
-                'X' => and_part |= 1,
-                '0' => {}
-                '1' => or_part |= 1,
-                _ => return Err(MaskParseError::UnexpectedCharacter(char)),
-            }
-        }
+/// A version 1 mask. Bits above the ones the mask covers are left alone,
+/// as if the mask had been padded with `X`s.
+struct Mask {
+    or_part: u128,
+    and_part: u128,
+    bits: u32,
+}
 
-        Ok(Mask { or_part, and_part })
+impl FromStr for Mask {
+    type Err = MaskParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (or_part, and_part, bits) = parse_mask(s)?;
+        Ok(Mask {
+            or_part,
+            and_part,
+            bits,
+        })
     }
 }
 
 impl Mask {
-    fn new() -> Mask {
-        Mask {
-            or_part: 0,
-            and_part: 0b1111_11111111_11111111_11111111_11111111,
-        }
-    }
-
-    fn mask(&self, value: u64) -> u64 {
-        (value & self.and_part) | self.or_part
+    fn mask(&self, value: u128) -> u128 {
+        (value & (self.and_part | !word_mask(self.bits))) | self.or_part
     }
 }
 
@@ -58,9 +90,36 @@ fn basic_mask_tests() {
     assert_eq!(64, test3.mask(0));
 }
 
+#[test]
+fn short_and_hex_masks() {
+    let short = Mask::from_str("1XXXX0X").unwrap();
+    assert_eq!(7, short.bits);
+    assert_eq!(0b1_1000_0101_0001, short.mask(0b1_1000_0001_0011));
+
+    let hex = Mask::from_str("0xF0XX").unwrap();
+    assert_eq!(16, hex.bits);
+    assert_eq!(0xF0AB, hex.mask(0x12AB));
+    assert_eq!(0x3_F0AB, hex.mask(0x3_12AB));
+
+    assert!(matches!(
+        Mask::from_str("0xG"),
+        Err(MaskParseError::UnexpectedCharacter('G'))
+    ));
+    assert!(matches!(
+        Mask::from_str("2"),
+        Err(MaskParseError::UnexpectedCharacter('2'))
+    ));
+    assert!(matches!(
+        Mask::from_str(&"X".repeat(129)),
+        Err(MaskParseError::TooLong(129))
+    ));
+    assert!(matches!(Mask::from_str(""), Err(MaskParseError::Empty)));
+    assert!(matches!(Mask::from_str("0x"), Err(MaskParseError::Empty)));
+}
+
 enum Command<M> {
     SetMask(M),
-    WriteMemory(usize, u64),
+    WriteMemory(u128, u128),
 }
 
 impl<M, E> FromStr for Command<M>
@@ -85,10 +144,10 @@ where
                     let numerics: String = left
                         .chars()
                         .skip(4)
-                        .take_while(|x| x.is_digit(10))
+                        .take_while(|x| x.is_ascii_digit())
                         .collect();
-                    let location = usize::from_str(&numerics)?;
-                    let value = u64::from_str(value_str)?;
+                    let location = u128::from_str(&numerics)?;
+                    let value = u128::from_str(value_str)?;
                     Ok(Command::WriteMemory(location, value))
                 }
             },
@@ -97,156 +156,859 @@ where
     }
 }
 
-struct Computer<M> {
-    mask: M,
-    locations: BTreeMap<usize, u64>,
+/// A set of addresses: every bit set in `floating` can be either 0 or 1,
+/// and the rest must match `fixed`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct AddressPattern {
+    fixed: u128,
+    floating: u128,
 }
 
-impl Computer<Mask> {
-    fn new() -> Computer<Mask> {
-        Computer {
-            mask: Mask::new(),
-            locations: BTreeMap::new(),
+impl AddressPattern {
+    fn exact(address: u128) -> AddressPattern {
+        AddressPattern {
+            fixed: address,
+            floating: 0,
+        }
+    }
+
+    /// `None` if every one of the 128 bits floats.
+    fn count(&self) -> Option<u128> {
+        1u128.checked_shl(self.floating.count_ones())
+    }
+
+    fn contains(&self, address: u128) -> bool {
+        (address & !self.floating) == self.fixed
+    }
+
+    /// Every address in the pattern, in increasing order.
+    fn addresses(&self) -> impl Iterator<Item = u128> {
+        let AddressPattern { fixed, floating } = *self;
+        std::iter::successors(Some(0u128), move |sub| {
+            if *sub == floating {
+                None
+            } else {
+                Some(sub.wrapping_sub(floating) & floating)
+            }
+        })
+        .map(move |sub| fixed | sub)
+    }
+
+    fn overlaps(&self, other: &AddressPattern) -> bool {
+        let both_fixed = !self.floating & !other.floating;
+        (self.fixed & both_fixed) == (other.fixed & both_fixed)
+    }
+
+    /// The addresses in both patterns, if there are any.
+    fn intersect(&self, other: &AddressPattern) -> Option<AddressPattern> {
+        if !self.overlaps(other) {
+            return None;
+        }
+
+        Some(AddressPattern {
+            fixed: self.fixed | other.fixed,
+            floating: self.floating & other.floating,
+        })
+    }
+
+    /// A single address in decimal, otherwise `width` binary digits with
+    /// an `X` for each floating bit.
+    fn show(&self, width: u32) -> String {
+        if self.floating == 0 {
+            return self.fixed.to_string();
+        }
+
+        (0..width)
+            .rev()
+            .map(|bit| {
+                if self.floating & (1 << bit) != 0 {
+                    'X'
+                } else if self.fixed & (1 << bit) != 0 {
+                    '1'
+                } else {
+                    '0'
+                }
+            })
+            .collect()
+    }
+
+    /// The addresses in `self` but not in `other`, as disjoint patterns. We
+    /// peel off one bit at a time: every bit that floats here but is fixed
+    /// in `other` splits off the half that `other` can't touch.
+    fn subtract(&self, other: &AddressPattern) -> Vec<AddressPattern> {
+        if !self.overlaps(other) {
+            return vec![*self];
+        }
+
+        let mut result = Vec::new();
+        let mut remaining = *self;
+        let splits = self.floating & !other.floating;
+
+        for bit in 0..128 {
+            let bitmask = 1u128 << bit;
+
+            if splits & bitmask != 0 {
+                remaining.floating &= !bitmask;
+                result.push(AddressPattern {
+                    fixed: remaining.fixed | (!other.fixed & bitmask),
+                    floating: remaining.floating,
+                });
+                remaining.fixed |= other.fixed & bitmask;
+            }
+        }
+
+        result
+    }
+}
+
+/// What's at one address, and the line of the command that put it there.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Cell {
+    value: u128,
+    line: usize,
+}
+
+/// Memory as disjoint address patterns, each holding one value. Single
+/// addresses, which are all version 1 ever writes, are kept in `exact` so
+/// that writing one doesn't mean splitting every pattern we already have.
+struct Memory {
+    exact: BTreeMap<u128, Cell>,
+    floating: Vec<(AddressPattern, Cell)>,
+}
+
+impl Memory {
+    fn new() -> Memory {
+        Memory {
+            exact: BTreeMap::new(),
+            floating: Vec::new(),
+        }
+    }
+
+    fn write(&mut self, pattern: AddressPattern, value: u128, line: usize) {
+        let cell = Cell { value, line };
+
+        if pattern.floating == 0 {
+            if self.floating.iter().any(|(x, _)| x.overlaps(&pattern)) {
+                self.forget(&pattern);
+            }
+            self.exact.insert(pattern.fixed, cell);
+        } else {
+            self.exact.retain(|address, _| !pattern.contains(*address));
+            self.forget(&pattern);
+            self.floating.push((pattern, cell));
+        }
+    }
+
+    /// Takes `pattern` out of every floating region.
+    fn forget(&mut self, pattern: &AddressPattern) {
+        let mut floating = Vec::with_capacity(self.floating.len() + 1);
+
+        for (existing, old_cell) in self.floating.drain(..) {
+            for remaining in existing.subtract(pattern) {
+                floating.push((remaining, old_cell));
+            }
+        }
+
+        self.floating = floating;
+    }
+
+    /// Every region, single addresses first.
+    fn regions(&self) -> impl Iterator<Item = (AddressPattern, Cell)> + '_ {
+        self.exact
+            .iter()
+            .map(|(address, cell)| (AddressPattern::exact(*address), *cell))
+            .chain(self.floating.iter().copied())
+    }
+
+    /// `None` if the sum doesn't fit in a `u128`, which wide words make
+    /// easy.
+    fn sum(&self) -> Option<u128> {
+        self.regions().try_fold(0u128, |total, (pattern, cell)| {
+            pattern.count()?.checked_mul(cell.value)?.checked_add(total)
+        })
+    }
+
+    /// Every written address, or `None` if there are more than `DUMP_LIMIT`.
+    fn cells(&self) -> Option<BTreeMap<u128, Cell>> {
+        let mut total = self.exact.len() as u128;
+        for (pattern, _) in self.floating.iter() {
+            total = total.checked_add(pattern.count()?)?;
+        }
+        if total > DUMP_LIMIT {
+            return None;
+        }
+
+        let mut result = self.exact.clone();
+        for (pattern, cell) in self.floating.iter() {
+            for address in pattern.addresses() {
+                result.insert(address, *cell);
+            }
+        }
+        Some(result)
+    }
+}
+
+#[test]
+fn pattern_memory() {
+    let mut memory = Memory::new();
+    memory.write(
+        AddressPattern {
+            fixed: 0b0000,
+            floating: 0b1111,
+        },
+        1,
+        1,
+    );
+    memory.write(AddressPattern::exact(0b0101), 10, 2);
+    memory.write(
+        AddressPattern {
+            fixed: 0b1000,
+            floating: 0b0011,
+        },
+        100,
+        3,
+    );
+    assert_eq!(Some(11 + 10 + 400), memory.sum());
+    let cells = memory.cells().unwrap();
+    assert_eq!(16, cells.len());
+    assert_eq!(Some(&Cell { value: 1, line: 1 }), cells.get(&0b0000));
+    assert_eq!(Some(&Cell { value: 10, line: 2 }), cells.get(&0b0101));
+    assert_eq!(
+        Some(&Cell {
+            value: 100,
+            line: 3
+        }),
+        cells.get(&0b1011)
+    );
+    memory.write(
+        AddressPattern {
+            fixed: 0,
+            floating: (1 << 40) - 1,
+        },
+        2,
+        4,
+    );
+    assert!(memory.exact.is_empty());
+    assert_eq!(1, memory.floating.len());
+    assert_eq!(Some(2 << 40), memory.sum());
+    assert_eq!(None, memory.cells());
+    memory.write(
+        AddressPattern {
+            fixed: 0,
+            floating: u128::MAX,
+        },
+        1,
+        5,
+    );
+    assert_eq!(None, memory.sum());
+}
+
+/// A chip revision: how the current mask turns a write into the addresses
+/// it touches and the value that goes in them. New masks that don't fit in
+/// the word are rejected before they get here.
+trait Decoder: FromStr<Err = MaskParseError> {
+    /// The mask in effect before the program sets one.
+    fn new() -> Self;
+    fn bits(&self) -> u32;
+    fn decode(&mut self, address: u128, value: u128, width: u32) -> (AddressPattern, u128);
+}
+
+/// Values and addresses wider than the word are cut down to it, the way the
+/// hardware would. `line` counts the commands run so far, one per line of
+/// the program.
+struct Computer<D> {
+    decoder: D,
+    memory: Memory,
+    width: u32,
+    line: usize,
+}
+
+impl<D: Decoder> Computer<D> {
+    fn new(width: u32) -> Result<Computer<D>, ComputerError> {
+        if !(8..=128).contains(&width) {
+            return Err(ComputerError::UnsupportedWidth(width));
         }
+
+        Ok(Computer {
+            decoder: D::new(),
+            memory: Memory::new(),
+            width,
+            line: 0,
+        })
     }
 
-    fn step(&mut self, m: Command<Mask>) {
+    fn step(&mut self, m: Command<D>) -> Result<(), ComputerError> {
+        let word = word_mask(self.width);
+        self.line += 1;
+
         match m {
-            Command::SetMask(new_mask) => self.mask = new_mask,
+            Command::SetMask(new_decoder) => {
+                if new_decoder.bits() > self.width {
+                    return Err(ComputerError::WiderThanWord(new_decoder.bits(), self.width));
+                }
+                self.decoder = new_decoder;
+            }
             Command::WriteMemory(location, value) => {
-                let _ = self.locations.insert(location, self.mask.mask(value));
+                let (address, value) =
+                    self.decoder
+                        .decode(location & word, value & word, self.width);
+                let address = AddressPattern {
+                    fixed: address.fixed & word,
+                    floating: address.floating & word,
+                };
+                self.memory.write(address, value & word, self.line);
             }
         }
+
+        Ok(())
     }
 }
 
+impl Decoder for Mask {
+    fn new() -> Mask {
+        Mask {
+            or_part: 0,
+            and_part: 0,
+            bits: 0,
+        }
+    }
+
+    fn bits(&self) -> u32 {
+        self.bits
+    }
+
+    fn decode(&mut self, address: u128, value: u128, _width: u32) -> (AddressPattern, u128) {
+        (AddressPattern::exact(address), self.mask(value))
+    }
+}
+
+/// A version 2 mask. Bits above the ones the mask covers are left alone,
+/// as if the mask had been padded with `0`s.
 struct FloatyMask {
-    or_part: usize,
-    floating_bits: Vec<usize>,
+    or_part: u128,
+    floating: u128,
+    bits: u32,
 }
 
 impl FromStr for FloatyMask {
     type Err = MaskParseError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        if s.len() != 36 {
-            return Err(MaskParseError::WrongLength(s.len()));
-        }
-
-        let mut bitno = 36;
-        let mut or_part = 0;
-        let mut floating_bits = Vec::new();
-
-        for char in s.chars() {
-            or_part <<= 1;
-            bitno -= 1;
-            match char {
-                'X' => floating_bits.push(bitno),
-                '0' => {}
-                '1' => or_part |= 1,
-                _ => return Err(MaskParseError::UnexpectedCharacter(char)),
-            }
-        }
-
+        let (or_part, floating, bits) = parse_mask(s)?;
         Ok(FloatyMask {
             or_part,
-            floating_bits,
+            floating,
+            bits,
         })
     }
 }
 
 impl FloatyMask {
+    fn mask(&self, value: u128) -> AddressPattern {
+        AddressPattern {
+            fixed: (value | self.or_part) & !self.floating,
+            floating: self.floating,
+        }
+    }
+}
+
+impl Decoder for FloatyMask {
     fn new() -> FloatyMask {
         FloatyMask {
             or_part: 0,
-            floating_bits: vec![],
+            floating: 0,
+            bits: 0,
         }
     }
 
-    fn mask(&self, value: usize) -> Vec<usize> {
-        let base = value | self.or_part;
-        let mut variants = vec![base];
+    fn bits(&self) -> u32 {
+        self.bits
+    }
+
+    fn decode(&mut self, address: u128, value: u128, _width: u32) -> (AddressPattern, u128) {
+        (self.mask(address), value)
+    }
+}
 
-        for bit in self.floating_bits.iter() {
-            let mut variants_zero = variants.clone();
+/// Masks the value like version 1 and the address like version 2, both
+/// with the same mask.
+struct DualMask {
+    value: Mask,
+    address: FloatyMask,
+}
 
-            for value in variants.iter_mut() {
-                *value |= 1 << bit;
-            }
-            for value in variants_zero.iter_mut() {
-                *value &= !(1 << bit);
-            }
+impl FromStr for DualMask {
+    type Err = MaskParseError;
 
-            variants.append(&mut variants_zero);
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(DualMask {
+            value: Mask::from_str(s)?,
+            address: FloatyMask::from_str(s)?,
+        })
+    }
+}
+
+impl Decoder for DualMask {
+    fn new() -> DualMask {
+        DualMask {
+            value: Mask::new(),
+            address: FloatyMask::new(),
         }
+    }
 
-        variants
+    fn bits(&self) -> u32 {
+        self.value.bits
+    }
+
+    fn decode(&mut self, address: u128, value: u128, _width: u32) -> (AddressPattern, u128) {
+        (self.address.mask(address), self.value.mask(value))
     }
 }
 
-impl Computer<FloatyMask> {
-    fn new() -> Computer<FloatyMask> {
-        Computer {
-            mask: FloatyMask::new(),
-            locations: BTreeMap::new(),
-        }
+/// Flips every value bit that's `1` in the mask; `0` and `X` both leave
+/// the bit alone.
+struct XorMask {
+    flip: u128,
+    bits: u32,
+}
+
+impl FromStr for XorMask {
+    type Err = MaskParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (flip, _, bits) = parse_mask(s)?;
+        Ok(XorMask { flip, bits })
     }
+}
 
-    fn step(&mut self, m: Command<FloatyMask>) {
-        match m {
-            Command::SetMask(new_mask) => self.mask = new_mask,
-            Command::WriteMemory(location, value) => {
-                for location in self.mask.mask(location) {
-                    self.locations.insert(location, value);
-                }
-            }
+impl Decoder for XorMask {
+    fn new() -> XorMask {
+        XorMask { flip: 0, bits: 0 }
+    }
+
+    fn bits(&self) -> u32 {
+        self.bits
+    }
+
+    fn decode(&mut self, address: u128, value: u128, _width: u32) -> (AddressPattern, u128) {
+        (AddressPattern::exact(address), value ^ self.flip)
+    }
+}
+
+fn rotate_left(value: u128, by: u32, width: u32) -> u128 {
+    if by == 0 {
+        value
+    } else {
+        ((value << by) | (value >> (width - by))) & word_mask(width)
+    }
+}
+
+/// A version 1 mask that turns one bit to the left, around the word, after
+/// every write. Setting a new mask starts it back where it was written.
+/// Only the turns within the word are kept, so it can go round forever.
+struct RotatingMask {
+    mask: Mask,
+    turns: u32,
+}
+
+impl FromStr for RotatingMask {
+    type Err = MaskParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(RotatingMask {
+            mask: Mask::from_str(s)?,
+            turns: 0,
+        })
+    }
+}
+
+impl Decoder for RotatingMask {
+    fn new() -> RotatingMask {
+        RotatingMask {
+            mask: Mask::new(),
+            turns: 0,
         }
     }
+
+    fn bits(&self) -> u32 {
+        self.mask.bits
+    }
+
+    fn decode(&mut self, address: u128, value: u128, width: u32) -> (AddressPattern, u128) {
+        let word = word_mask(width);
+        let by = self.turns;
+        let or_part = rotate_left(self.mask.or_part, by, width);
+        let and_part = rotate_left(
+            (self.mask.and_part | !word_mask(self.mask.bits)) & word,
+            by,
+            width,
+        );
+
+        self.turns = (self.turns + 1) % width;
+        (AddressPattern::exact(address), (value & and_part) | or_part)
+    }
 }
 
 #[test]
 fn floaty_mask() {
     let test1 = FloatyMask::from_str("000000000000000000000000000000X1001X").unwrap();
-    assert_eq!(2, test1.floating_bits.len());
+    assert_eq!(2, test1.floating.count_ones());
     let result1 = test1.mask(42);
-    assert_eq!(4, result1.len());
-    assert!(result1.contains(&26));
-    assert!(result1.contains(&27));
-    assert!(result1.contains(&58));
-    assert!(result1.contains(&59));
+    assert_eq!(Some(4), result1.count());
+    assert!(result1.contains(26));
+    assert!(result1.contains(27));
+    assert!(result1.contains(58));
+    assert!(result1.contains(59));
 
     let test2 = FloatyMask::from_str("00000000000000000000000000000000X0XX").unwrap();
-    assert_eq!(3, test2.floating_bits.len());
+    assert_eq!(3, test2.floating.count_ones());
     let result2 = test2.mask(26);
-    assert_eq!(8, result2.len());
-    assert!(result2.contains(&16));
-    assert!(result2.contains(&17));
-    assert!(result2.contains(&18));
-    assert!(result2.contains(&19));
-    assert!(result2.contains(&24));
-    assert!(result2.contains(&25));
-    assert!(result2.contains(&26));
-    assert!(result2.contains(&27));
+    assert_eq!(Some(8), result2.count());
+    assert!(result2.contains(16));
+    assert!(result2.contains(17));
+    assert!(result2.contains(18));
+    assert!(result2.contains(19));
+    assert!(result2.contains(24));
+    assert!(result2.contains(25));
+    assert!(result2.contains(26));
+    assert!(result2.contains(27));
 }
 
-fn main() -> Result<(), TopLevelError> {
-    let filename = env::args().nth(1).expect("No file argument given.");
-    let contents = fs::read_to_string(filename)?;
-    let mut computer1 = Computer::<Mask>::new();
-    let mut computer2 = Computer::<FloatyMask>::new();
+#[test]
+fn floaty_example() {
+    let contents = fs::read_to_string("inputs/day14_test2.txt").unwrap();
+    let mut computer = Computer::<FloatyMask>::new(DEFAULT_WIDTH).unwrap();
+    for line in contents.lines() {
+        computer
+            .step(Command::<FloatyMask>::from_str(line).unwrap())
+            .unwrap();
+    }
+    assert_eq!(Some(208), computer.memory.sum());
+}
+
+#[test]
+fn word_widths() {
+    assert!(Computer::<Mask>::new(7).is_err());
+    assert!(Computer::<Mask>::new(129).is_err());
+
+    let mut narrow = Computer::<Mask>::new(16).unwrap();
+    narrow
+        .step(Command::from_str("mask = 0x0XXX").unwrap())
+        .unwrap();
+    narrow
+        .step(Command::from_str("mem[65537] = 74565").unwrap())
+        .unwrap();
+    assert_eq!(Some(0x0345), narrow.memory.sum());
+    assert_eq!(
+        Some(&Cell {
+            value: 0x0345,
+            line: 2
+        }),
+        narrow.memory.exact.get(&1)
+    );
+    assert!(matches!(
+        narrow.step(Command::from_str("mask = 0x0XXXX").unwrap()),
+        Err(ComputerError::WiderThanWord(20, 16))
+    ));
+
+    let mut wide = Computer::<FloatyMask>::new(64).unwrap();
+    wide.step(Command::from_str("mask = 1X").unwrap()).unwrap();
+    wide.step(Command::from_str("mem[4294967296] = 3").unwrap())
+        .unwrap();
+    let address = wide.memory.floating[0].0;
+    assert!(address.contains((1 << 32) | 0b10));
+    assert!(address.contains((1 << 32) | 0b11));
+    assert_eq!(Some(6), wide.memory.sum());
+}
+
+#[test]
+fn other_decoders() {
+    let mut dual = Computer::<DualMask>::new(8).unwrap();
+    dual.step(Command::from_str("mask = 1X0X").unwrap())
+        .unwrap();
+    dual.step(Command::from_str("mem[3] = 15").unwrap())
+        .unwrap();
+    let (address, cell) = dual.memory.floating[0];
+    assert_eq!(13, cell.value);
+    assert_eq!(Some(4), address.count());
+    assert!(address.contains(0b1010));
+    assert!(address.contains(0b1111));
+
+    let mut xor = Computer::<XorMask>::new(8).unwrap();
+    xor.step(Command::from_str("mask = 0x0F").unwrap()).unwrap();
+    xor.step(Command::from_str("mem[1] = 53").unwrap()).unwrap();
+    assert_eq!(Some(0x3A), xor.memory.sum());
+
+    let mut rotating = Computer::<RotatingMask>::new(8).unwrap();
+    rotating
+        .step(Command::from_str("mask = 1X").unwrap())
+        .unwrap();
+    for location in 0..9 {
+        rotating
+            .step(Command::WriteMemory(location, 0b1000_0000))
+            .unwrap();
+    }
+    assert_eq!(1, rotating.decoder.turns);
+    let values: Vec<u128> = rotating
+        .memory
+        .exact
+        .values()
+        .map(|cell| cell.value)
+        .collect();
+    assert_eq!(
+        vec![0x82, 0x84, 0x88, 0x90, 0xA0, 0xC0, 0x80, 0x81, 0x82],
+        values
+    );
+}
 
+fn hex_digits(width: u32) -> usize {
+    width.div_ceil(4) as usize
+}
+
+/// Eight cells to a row, skipping rows with nothing written in them.
+fn hexdump(cells: &BTreeMap<u128, Cell>, width: u32) -> String {
+    let digits = hex_digits(width);
+    let rows: BTreeSet<u128> = cells.keys().map(|x| x & !7).collect();
+    let mut result = String::new();
+
+    for row in rows {
+        write!(result, "{:0width$x}:", row, width = digits).unwrap();
+        for address in row..=row + 7 {
+            match cells.get(&address) {
+                Some(cell) => write!(result, " {:0width$x}", cell.value, width = digits).unwrap(),
+                None => write!(result, " {}", ".".repeat(digits)).unwrap(),
+            }
+        }
+        writeln!(result).unwrap();
+    }
+
+    result
+}
+
+/// Every region, ordered by its lowest address.
+fn sorted_regions(memory: &Memory) -> Vec<(AddressPattern, Cell)> {
+    let mut regions: Vec<(AddressPattern, Cell)> = memory.regions().collect();
+    regions.sort_by_key(|(pattern, _)| (pattern.fixed, pattern.floating));
+    regions
+}
+
+/// One row per region; `floating` is zero for a single address.
+fn csv(memory: &Memory) -> String {
+    let mut result = String::from("address,floating,value,line\n");
+    for (pattern, cell) in sorted_regions(memory) {
+        writeln!(
+            result,
+            "{},{},{},{}",
+            pattern.fixed, pattern.floating, cell.value, cell.line
+        )
+        .unwrap();
+    }
+    result
+}
+
+fn json(memory: &Memory) -> String {
+    let entries: Vec<String> = sorted_regions(memory)
+        .iter()
+        .map(|(pattern, cell)| {
+            format!(
+                "  {{\"address\":{},\"floating\":{},\"value\":{},\"line\":{}}}",
+                pattern.fixed, pattern.floating, cell.value, cell.line
+            )
+        })
+        .collect();
+    format!("[\n{}\n]\n", entries.join(",\n"))
+}
+
+fn describe(name: &str, cell: Option<&Cell>) -> String {
+    match cell {
+        None => format!("{} unset", name),
+        Some(cell) => format!("{} {} (line {})", name, cell.value, cell.line),
+    }
+}
+
+/// The parts of `pattern` that nothing in `memory` was written to.
+fn unwritten(pattern: AddressPattern, memory: &Memory) -> Vec<AddressPattern> {
+    let mut remaining = vec![pattern];
+
+    for (other, _) in memory.regions() {
+        if remaining.iter().any(|x| x.overlaps(&other)) {
+            remaining = remaining.iter().flat_map(|x| x.subtract(&other)).collect();
+        }
+    }
+
+    remaining
+}
+
+/// Every region where the two memories disagree. We work on the patterns
+/// themselves, so this never has to list the addresses in them.
+fn diff(
+    (left_name, left): (&str, &Memory),
+    (right_name, right): (&str, &Memory),
+    width: u32,
+) -> String {
+    let mut differences = Vec::new();
+
+    for (l, l_cell) in left.regions() {
+        for (r, r_cell) in right.regions() {
+            if l_cell.value != r_cell.value {
+                if let Some(both) = l.intersect(&r) {
+                    differences.push((both, Some(l_cell), Some(r_cell)));
+                }
+            }
+        }
+        for only in unwritten(l, right) {
+            differences.push((only, Some(l_cell), None));
+        }
+    }
+    for (r, r_cell) in right.regions() {
+        for only in unwritten(r, left) {
+            differences.push((only, None, Some(r_cell)));
+        }
+    }
+
+    differences.sort_by_key(|(pattern, _, _)| (pattern.fixed, pattern.floating));
+    let mut result = String::new();
+
+    for (pattern, l, r) in differences {
+        writeln!(
+            result,
+            "{}: {}, {}",
+            pattern.show(width),
+            describe(left_name, l.as_ref()),
+            describe(right_name, r.as_ref())
+        )
+        .unwrap();
+    }
+
+    result
+}
+
+#[test]
+fn dumps() {
+    let contents = fs::read_to_string("inputs/day14_test2.txt").unwrap();
+    let mut computer1 = Computer::<Mask>::new(DEFAULT_WIDTH).unwrap();
+    let mut computer2 = Computer::<FloatyMask>::new(DEFAULT_WIDTH).unwrap();
     for line in contents.lines() {
-        computer1.step(Command::<Mask>::from_str(line)?);
-        computer2.step(Command::<FloatyMask>::from_str(line)?);
+        computer1.step(Command::from_str(line).unwrap()).unwrap();
+        computer2.step(Command::from_str(line).unwrap()).unwrap();
     }
 
-    println!(
-        "Sum of all memory values in computer #1: {}",
-        computer1.locations.values().sum::<u64>()
+    let dump = hexdump(&computer2.memory.cells().unwrap(), 16);
+    assert_eq!(
+        "0010: 0001 0001 0001 0001 .... .... .... ....\n\
+         0018: 0001 0001 0001 0001 .... .... .... ....\n\
+         0038: .... .... 0064 0064 .... .... .... ....\n",
+        dump
     );
-    println!(
-        "Sum of all memory values in computer #2: {}",
-        computer2.locations.values().sum::<u64>()
+    assert!(csv(&computer2.memory).starts_with("address,floating,value,line\n16,11,1,4\n"));
+    assert!(
+        json(&computer1.memory).contains("{\"address\":42,\"floating\":0,\"value\":50,\"line\":2}")
     );
+
+    let differences = diff(
+        ("v1", &computer1.memory),
+        ("v2", &computer2.memory),
+        DEFAULT_WIDTH,
+    );
+    assert!(!differences.lines().any(|x| x.starts_with("26:")));
+    assert!(differences.contains("42: v1 50 (line 2), v2 unset\n"));
+    assert!(differences.contains(&format!("{:0>36}: v1 unset, v2 100 (line 2)\n", "11101X")));
+
+    let mut top = Computer::<Mask>::new(128).unwrap();
+    top.step(Command::WriteMemory(u128::MAX, 1)).unwrap();
+    assert!(
+        hexdump(&top.memory.cells().unwrap(), 128).ends_with(" 00000000000000000000000000000001\n")
+    );
+}
+
+fn run<D: Decoder>(contents: &str, width: u32) -> Result<Memory, TopLevelError> {
+    let mut computer = Computer::<D>::new(width)?;
+    for line in contents.lines() {
+        computer.step(Command::<D>::from_str(line)?)?;
+    }
+    Ok(computer.memory)
+}
+
+fn run_named(name: &str, contents: &str, width: u32) -> Result<Memory, TopLevelError> {
+    match name {
+        "v1" => run::<Mask>(contents, width),
+        "v2" => run::<FloatyMask>(contents, width),
+        "dual" => run::<DualMask>(contents, width),
+        "xor" => run::<XorMask>(contents, width),
+        "rotate" => run::<RotatingMask>(contents, width),
+        _ => Err(TopLevelError::BadUsage(format!(
+            "unknown decoder {}; try {}",
+            name, DECODERS
+        ))),
+    }
+}
+
+const DECODERS: &str = "v1, v2, dual, xor or rotate";
+
+fn main() -> Result<(), TopLevelError> {
+    let filename = env::args().nth(1).expect("No file argument given.");
+    // an optional word width, then an optional mode
+    let mut args: Vec<String> = env::args().skip(2).collect();
+    let width = match args.first().map(|x| u32::from_str(x)) {
+        Some(Ok(width)) => {
+            args.remove(0);
+            width
+        }
+        _ => DEFAULT_WIDTH,
+    };
+    let contents = fs::read_to_string(filename)?;
+
+    let args: Vec<&str> = args.iter().map(|x| x.as_str()).collect();
+    match args.as_slice() {
+        ["dump", format, rest @ ..] if rest.len() <= 1 => {
+            let decoder = rest.first().copied().unwrap_or("v1");
+            let memory = run_named(decoder, &contents, width)?;
+            match *format {
+                "hex" => match memory.cells() {
+                    Some(cells) => print!("{}", hexdump(&cells, width)),
+                    None => println!(
+                        "More than {} addresses written; too many to dump.",
+                        DUMP_LIMIT
+                    ),
+                },
+                "csv" => print!("{}", csv(&memory)),
+                "json" => print!("{}", json(&memory)),
+                _ => {
+                    return Err(TopLevelError::BadUsage(format!(
+                        "unknown dump format {}; try hex, csv or json",
+                        format
+                    )))
+                }
+            }
+        }
+        ["diff", rest @ ..] if rest.len() <= 2 => {
+            let left = rest.first().copied().unwrap_or("v1");
+            let right = rest.get(1).copied().unwrap_or("v2");
+            let l = run_named(left, &contents, width)?;
+            let r = run_named(right, &contents, width)?;
+            print!("{}", diff((left, &l), (right, &r), width));
+        }
+        [] => {
+            let memories = [
+                ("#1", run::<Mask>(&contents, width)?),
+                ("#2", run::<FloatyMask>(&contents, width)?),
+            ];
+            for (name, memory) in memories.iter() {
+                match memory.sum() {
+                    Some(sum) => {
+                        println!("Sum of all memory values in computer {}: {}", name, sum)
+                    }
+                    None => println!("Sum of all memory values in computer {} overflows", name),
+                }
+            }
+        }
+        _ => {
+            return Err(TopLevelError::BadUsage(format!(
+                "unexpected arguments {:?}; try dump FORMAT [DECODER] or diff [DECODER] [DECODER]",
+                args
+            )))
+        }
+    }
+
     Ok(())
 }
@@ -51,7 +51,7 @@ impl FromStr for Seat {
         for direction in row_stuff.chars() {
             match direction {
                 'F' => row_high = row_low + ((row_high - row_low) / 2),
-                'B' => row_low = row_low + ((row_high - row_low + 1) / 2),
+                'B' => row_low = row_low + (row_high - row_low).div_ceil(2),
                 _ => return Err(SeatParseError::UnexpectedRowCharacter(direction)),
             }
         }
@@ -66,7 +66,7 @@ impl FromStr for Seat {
         for direction in column_stuff.chars() {
             match direction {
                 'L' => column_high = column_low + ((column_high - column_low) / 2),
-                'R' => column_low = column_low + ((column_high - column_low + 1) / 2),
+                'R' => column_low = column_low + (column_high - column_low).div_ceil(2),
                 _ => return Err(SeatParseError::UnexpectedColumnCharacter(direction)),
             }
         }
@@ -121,22 +121,22 @@ fn example_boarding_passes() {
 
 fn main() -> Result<(), TopLevelError> {
     let mut seats = BTreeSet::new();
-    let mut highest_id = 0;
 
     for argument in env::args().skip(1) {
         let contents = fs::read_to_string(argument)?;
 
         for line in contents.lines() {
-            let seat = Seat::from_str(line)?;
-            if seat.id > highest_id {
-                highest_id = seat.id;
-            }
-            seats.insert(seat);
+            seats.insert(Seat::from_str(line)?);
         }
     }
 
     println!("Loaded {} seats.", seats.len());
-    println!("  highest id is {}", highest_id);
+    if let Some(highest) = seats.iter().next_back() {
+        println!(
+            "  highest id is {} (row {}, column {})",
+            highest.id, highest.row, highest.column
+        );
+    }
     let mut last_id = 0;
     for seat in seats.iter() {
         if seat.id == last_id + 2 {
@@ -24,7 +24,7 @@ fn find_pattern(busses: &[(usize, usize)]) -> Result<usize, TopLevelError> {
 fn lcm(x: usize, y: usize) -> usize {
     let mut k = x;
 
-    while k % y != 0 {
+    while !k.is_multiple_of(y) {
         k += x;
     }
 
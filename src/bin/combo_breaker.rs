@@ -42,10 +42,10 @@ fn compute_encryption_key(card_public: usize, door_public: usize) -> usize {
 
 fn main() -> Result<(), TopLevelError> {
     println!("---- TEST CASE ----");
-    let test_key = compute_encryption_key(5764801, 17807724);
+    let _test_key = compute_encryption_key(5764801, 17807724);
     println!();
     println!("---- REAL INPUT ----");
-    let real_key = compute_encryption_key(12090988, 240583);
+    let _real_key = compute_encryption_key(12090988, 240583);
     println!();
     Ok(())
 }
\ No newline at end of file
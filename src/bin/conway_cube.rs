@@ -215,7 +215,7 @@ impl Conway4Cube {
     }
 }
 
-impl<'a> From<&'a ConwayCube> for Conway4Cube {
+impl From<&ConwayCube> for Conway4Cube {
     fn from(x: &ConwayCube) -> Conway4Cube {
         Conway4Cube {
             x_range: x.x_range.clone(),
@@ -1,28 +1,32 @@
-use advent2020::errors::TopLevelError;
+use advent2020::errors::{CombatError, TopLevelError};
+use advent2020::random::Random;
+use std::collections::hash_map::DefaultHasher;
 use std::env;
 use std::fmt;
 use std::fs;
+use std::hash::{Hash, Hasher};
 use std::iter::FromIterator;
 use std::str::FromStr;
 use std::{
-    collections::{HashMap, VecDeque},
+    collections::{BTreeMap, HashMap, HashSet, VecDeque},
     sync::atomic::{AtomicUsize, Ordering},
 };
 
-#[derive(Clone, Eq, Hash, PartialEq)]
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
 struct Deck {
     player: usize,
     cards: VecDeque<usize>,
 }
 
 impl Deck {
-    fn read<'a, I: Iterator<Item = &'a str>>(lines: &mut I) -> Result<Deck, TopLevelError> {
+    fn read<'a, I: Iterator<Item = &'a str>>(lines: &mut I) -> Result<Option<Deck>, CombatError> {
         match lines.next() {
-            None => Err(TopLevelError::NoInputFound),
+            None => Ok(None),
             Some("") => Deck::read(lines),
             Some(x) if x.starts_with("Player ") => {
                 let numeric_string = x.trim_start_matches("Player ").trim_end_matches(':');
-                let player = usize::from_str(numeric_string)?;
+                let player = usize::from_str(numeric_string)
+                    .map_err(|_| CombatError::BadPlayerLine(x.to_string()))?;
                 let mut cards = VecDeque::new();
 
                 loop {
@@ -33,9 +37,9 @@ impl Deck {
                     }
                 }
 
-                Ok(Deck { player, cards })
+                Ok(Some(Deck { player, cards }))
             }
-            Some(_) => Err(TopLevelError::UnknownError),
+            Some(x) => Err(CombatError::BadPlayerLine(x.to_string())),
         }
     }
 
@@ -44,12 +48,13 @@ impl Deck {
     }
 
     fn add_cards(&mut self, winning_card: usize, cards: &mut Vec<usize>) {
+        if let Some(idx) = cards.iter().position(|x| *x == winning_card) {
+            cards.swap_remove(idx);
+        }
         cards.sort_unstable();
         cards.reverse();
         self.cards.push_back(winning_card);
-        for card in cards.drain(..).filter(|x| *x != winning_card) {
-            self.cards.push_back(card);
-        }
+        self.cards.extend(cards.drain(..));
     }
 
     fn size(&self) -> usize {
@@ -67,25 +72,430 @@ impl fmt::Display for Deck {
     }
 }
 
-struct Game {
+/// Every `Player N:` section in the input, in the order they appear.
+fn read_decks(contents: &str) -> Result<Vec<Deck>, CombatError> {
+    let mut lines = contents.lines();
+    let mut players = HashSet::new();
+    let mut result = Vec::new();
+
+    while let Some(deck) = Deck::read(&mut lines)? {
+        if !players.insert(deck.player) {
+            return Err(CombatError::DuplicatePlayer(deck.player));
+        }
+        result.push(deck);
+    }
+
+    if result.len() < 2 {
+        return Err(CombatError::NotEnoughPlayers(result.len()));
+    }
+    Ok(result)
+}
+
+/// How a round's cards compared.
+#[derive(Debug, PartialEq)]
+enum Outcome {
+    Winner(usize),
+    Tie(Vec<usize>),
+}
+
+/// The player or players who played `best`.
+fn settle(played: &[(usize, usize)], best: usize) -> Outcome {
+    let tied: Vec<usize> = played
+        .iter()
+        .filter(|(_, card)| *card == best)
+        .map(|(player, _)| *player)
+        .collect();
+
+    if tied.len() == 1 {
+        Outcome::Winner(tied[0])
+    } else {
+        Outcome::Tie(tied)
+    }
+}
+
+/// The rules for settling a round of Combat. Only `judge` is required; the
+/// rest default to the plain game, with no sub-games, no wars and no limit
+/// on repeats.
+trait RoundRule {
+    /// Who wins a round that isn't settled by a sub-game, given each
+    /// player's card as `(player, card)`, in player order.
+    fn judge(&self, played: &[(usize, usize)]) -> Outcome;
+
+    /// How many cards a player takes into a sub-game, given the card they
+    /// played and how many they have left. The round goes to a sub-game only
+    /// if every player gets `Some`.
+    fn recursion(&self, _card: usize, _remaining: usize) -> Option<usize> {
+        None
+    }
+
+    /// How many cards tied players lay face down before playing again, or
+    /// `None` if ties can't be settled.
+    fn war(&self) -> Option<usize> {
+        None
+    }
+
+    /// Which of the players still holding cards wins when a game comes back
+    /// to a position it's seen before, or `None` if repeats don't end games.
+    fn repeat_winner(&self, _players: &[usize]) -> Option<usize> {
+        None
+    }
+
+    /// A sub-game's winner, if these rules can tell without playing it.
+    fn known_winner(&self, _decks: &[Deck]) -> Option<usize> {
+        None
+    }
+}
+
+/// The original game: the highest card takes the round.
+struct HighestCard;
+
+impl RoundRule for HighestCard {
+    fn judge(&self, played: &[(usize, usize)]) -> Outcome {
+        settle(played, played.iter().map(|x| x.1).max().unwrap_or(0))
+    }
+}
+
+/// The lowest card takes the round.
+struct LowestCard;
+
+impl RoundRule for LowestCard {
+    fn judge(&self, played: &[(usize, usize)]) -> Outcome {
+        settle(played, played.iter().map(|x| x.1).min().unwrap_or(0))
+    }
+}
+
+/// Some other rule, but tied players lay `face_down` cards down and play
+/// again, with the winner taking everything on the table.
+struct War<R> {
+    base: R,
+    face_down: usize,
+}
+
+impl<R: RoundRule> RoundRule for War<R> {
+    fn judge(&self, played: &[(usize, usize)]) -> Outcome {
+        self.base.judge(played)
+    }
+
+    fn war(&self) -> Option<usize> {
+        Some(self.face_down)
+    }
+}
+
+/// The puzzle's sub-game trigger: every player has at least as many cards
+/// left as the card they played, and takes that many into the sub-game.
+fn enough_cards(card: usize, remaining: usize) -> Option<usize> {
+    if remaining >= card {
+        Some(card)
+    } else {
+        None
+    }
+}
+
+/// Some other rule, but rounds go to a sub-game whenever `trigger` says
+/// so for every player, and a repeated position goes to the lowest-numbered
+/// player still in the game.
+struct Recursive<R> {
+    base: R,
+    trigger: fn(usize, usize) -> Option<usize>,
+    shortcut: bool,
+}
+
+impl Recursive<HighestCard> {
+    /// Recursive Combat exactly as the puzzle plays it.
+    fn standard() -> Recursive<HighestCard> {
+        Recursive {
+            base: HighestCard,
+            trigger: enough_cards,
+            shortcut: true,
+        }
+    }
+}
+
+impl<R: RoundRule> Recursive<R> {
+    fn with_trigger(base: R, trigger: fn(usize, usize) -> Option<usize>) -> Recursive<R> {
+        Recursive {
+            base,
+            trigger,
+            shortcut: false,
+        }
+    }
+}
+
+impl<R: RoundRule> RoundRule for Recursive<R> {
+    fn judge(&self, played: &[(usize, usize)]) -> Outcome {
+        self.base.judge(played)
+    }
+
+    fn recursion(&self, card: usize, remaining: usize) -> Option<usize> {
+        (self.trigger)(card, remaining)
+    }
+
+    fn war(&self) -> Option<usize> {
+        self.base.war()
+    }
+
+    fn repeat_winner(&self, players: &[usize]) -> Option<usize> {
+        players.iter().min().copied()
+    }
+
+    /// Only for the standard game, with two players and no card in both
+    /// hands (which we check, since reading the decks doesn't): the highest
+    /// card can only trigger a sub-game if there are more cards in the game
+    /// than its value, and there can't be; so it wins every round it's in,
+    /// and whoever holds it can't run out. If that's the player who'd win a
+    /// repeat, they win either way.
+    fn known_winner(&self, decks: &[Deck]) -> Option<usize> {
+        if !self.shortcut || decks.len() != 2 {
+            return None;
+        }
+
+        let mut cards: Vec<usize> = decks.iter().flat_map(|x| x.cards.iter().copied()).collect();
+        cards.sort_unstable();
+        if cards.windows(2).any(|x| x[0] == x[1]) {
+            return None;
+        }
+
+        let highest = decks
+            .iter()
+            .flat_map(|x| x.cards.iter().map(move |card| (*card, x.player)))
+            .max()?;
+        let first = decks.iter().map(|x| x.player).min()?;
+        if highest.1 == first {
+            Some(first)
+        } else {
+            None
+        }
+    }
+}
+
+const RULES: &str = "classic, lowest, war, recursive or recursive-war";
+
+fn named_rule(name: &str) -> Option<Box<dyn RoundRule>> {
+    match name {
+        "classic" => Some(Box::new(HighestCard)),
+        "lowest" => Some(Box::new(LowestCard)),
+        "war" => Some(Box::new(War {
+            base: HighestCard,
+            face_down: 3,
+        })),
+        "recursive" => Some(Box::new(Recursive::standard())),
+        "recursive-war" => Some(Box::new(Recursive::with_trigger(
+            War {
+                base: HighestCard,
+                face_down: 3,
+            },
+            enough_cards,
+        ))),
+        _ => None,
+    }
+}
+
+/// How a round went to a sub-game.
+#[derive(Clone, Debug, PartialEq)]
+enum Recursion {
+    /// The sub-game was played, as the game with this id.
+    Played(usize),
+    /// The rules or an earlier sub-game already said who'd win.
+    Known,
+}
+
+/// Something that happened during a game. `Round`s are logged once they're
+/// settled, so a sub-game's events come before the round that started it.
+#[derive(Clone, Debug, PartialEq)]
+enum Event {
+    Round {
+        game: usize,
+        depth: usize,
+        round: usize,
+        played: Vec<(usize, usize)>,
+        wars: usize,
+        recursion: Option<Recursion>,
+        winner: usize,
+    },
+    GameOver {
+        game: usize,
+        depth: usize,
+        rounds: usize,
+        winner: usize,
+        repeated: bool,
+    },
+}
+
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Event::Round {
+                game,
+                depth,
+                round,
+                played,
+                wars,
+                recursion,
+                winner,
+            } => {
+                write!(f, "Game {} (depth {}), round {}:", game, depth, round)?;
+                for (player, card) in played.iter() {
+                    write!(f, " [Player {} plays {}]", player, card)?;
+                }
+                if *wars > 0 {
+                    write!(f, " {} war(s),", wars)?;
+                }
+                match recursion {
+                    Some(Recursion::Played(id)) => write!(f, " won sub-game {},", id)?,
+                    Some(Recursion::Known) => write!(f, " won a known sub-game,")?,
+                    None => {}
+                }
+                write!(f, " Player {} takes the round", winner)
+            }
+            Event::GameOver {
+                game,
+                depth,
+                rounds,
+                winner,
+                repeated,
+            } => write!(
+                f,
+                "Game {} (depth {}) over after {} rounds{}: Player {} wins",
+                game,
+                depth,
+                rounds,
+                if *repeated { " on a repeat" } else { "" },
+                winner
+            ),
+        }
+    }
+}
+
+/// Reads back what `Display` writes, so a logged game can be replayed.
+impl FromStr for Event {
+    type Err = CombatError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let bad = || CombatError::BadEvent(s.to_string());
+        let number = |x: &str| usize::from_str(x).map_err(|_| bad());
+
+        let rest = s.strip_prefix("Game ").ok_or_else(bad)?;
+        let (game, rest) = rest.split_once(" (depth ").ok_or_else(bad)?;
+        let (depth, rest) = rest.split_once(')').ok_or_else(bad)?;
+        let (game, depth) = (number(game)?, number(depth)?);
+
+        if let Some(rest) = rest.strip_prefix(" over after ") {
+            let (rounds, rest) = rest.split_once(" rounds").ok_or_else(bad)?;
+            let (repeated, rest) = match rest.strip_prefix(" on a repeat") {
+                Some(rest) => (true, rest),
+                None => (false, rest),
+            };
+            let winner = rest
+                .strip_prefix(": Player ")
+                .and_then(|x| x.strip_suffix(" wins"))
+                .ok_or_else(bad)?;
+            return Ok(Event::GameOver {
+                game,
+                depth,
+                rounds: number(rounds)?,
+                winner: number(winner)?,
+                repeated,
+            });
+        }
+
+        let rest = rest.strip_prefix(", round ").ok_or_else(bad)?;
+        let (round, mut rest) = rest.split_once(':').ok_or_else(bad)?;
+        let mut played = Vec::new();
+        while let Some(card) = rest.strip_prefix(" [Player ") {
+            let (player, card) = card.split_once(" plays ").ok_or_else(bad)?;
+            let (card, after) = card.split_once(']').ok_or_else(bad)?;
+            played.push((number(player)?, number(card)?));
+            rest = after;
+        }
+
+        let mut wars = 0;
+        if let Some((count, after)) = rest.split_once(" war(s),") {
+            wars = number(count.trim_start())?;
+            rest = after;
+        }
+
+        let recursion = if let Some(after) = rest.strip_prefix(" won a known sub-game,") {
+            rest = after;
+            Some(Recursion::Known)
+        } else if let Some(after) = rest.strip_prefix(" won sub-game ") {
+            let (id, after) = after.split_once(',').ok_or_else(bad)?;
+            rest = after;
+            Some(Recursion::Played(number(id)?))
+        } else {
+            None
+        };
+
+        let winner = rest
+            .strip_prefix(" Player ")
+            .and_then(|x| x.strip_suffix(" takes the round"))
+            .ok_or_else(bad)?;
+        Ok(Event::Round {
+            game,
+            depth,
+            round: number(round)?,
+            played,
+            wars,
+            recursion,
+            winner: number(winner)?,
+        })
+    }
+}
+
+/// Where a game sends its events.
+type Listener<'b> = &'b mut dyn FnMut(&Event);
+
+struct Game<'a> {
     id: usize,
+    depth: usize,
     round: usize,
-    history: Vec<HashMap<usize, Deck>>,
-    decks: HashMap<usize, Deck>,
+    seen: HashSet<Fingerprint>,
+    decks: BTreeMap<usize, Deck>,
+    rule: &'a dyn RoundRule,
+}
+
+type Fingerprint = u128;
+
+/// Every deck, in player order, hashed into 128 bits: two halves from two
+/// differently started hashers. Different decks could share a fingerprint,
+/// but at this size that isn't going to happen in any game we can play.
+fn fingerprint<'a, I: Iterator<Item = &'a Deck>>(decks: I) -> Fingerprint {
+    let mut decks: Vec<&Deck> = decks.collect();
+    let mut low = DefaultHasher::new();
+    let mut high = DefaultHasher::new();
+
+    high.write_u8(1);
+    decks.sort_unstable_by_key(|x| x.player);
+    for deck in decks {
+        deck.hash(&mut low);
+        deck.hash(&mut high);
+    }
+
+    ((high.finish() as u128) << 64) | low.finish() as u128
 }
 
+/// The winning player of every recursive game played so far, by the
+/// fingerprint of its starting decks. Only good for games under one rule,
+/// so each top-level game keeps its own.
+type Results = HashMap<Fingerprint, usize>;
+
 static NEXT_GAME_NUMBER: AtomicUsize = AtomicUsize::new(1);
 
-impl Game {
-    fn new(decks: &[Deck]) -> Game {
+impl<'a> Game<'a> {
+    fn new(decks: &[Deck], rule: &'a dyn RoundRule) -> Game<'a> {
         Game {
             id: NEXT_GAME_NUMBER.fetch_add(1, Ordering::SeqCst),
-            history: Vec::new(),
+            depth: 0,
+            seen: HashSet::new(),
             round: 0,
-            decks: HashMap::from_iter(decks.iter().map(|x| (x.player, x.clone()))),
+            rule,
+            decks: BTreeMap::from_iter(decks.iter().map(|x| (x.player, x.clone()))),
         }
     }
 
+    fn fingerprint(&self) -> Fingerprint {
+        fingerprint(self.decks.values())
+    }
+
     fn winner(&self) -> Option<&Deck> {
         let mut result = None;
 
@@ -100,112 +510,558 @@ impl Game {
         result
     }
 
-    fn play(&mut self, recursive: bool) -> Result<Deck, TopLevelError> {
+    fn game_over(&self, winner: usize, repeated: bool, listener: Listener) -> Deck {
+        listener(&Event::GameOver {
+            game: self.id,
+            depth: self.depth,
+            rounds: self.round,
+            winner,
+            repeated,
+        });
+        self.decks[&winner].clone()
+    }
+
+    fn play(&mut self, listener: Listener) -> Result<Deck, CombatError> {
+        self.play_with(&mut Results::new(), listener)
+    }
+
+    /// Plays on, sharing `results` with every sub-game.
+    fn play_with(
+        &mut self,
+        results: &mut Results,
+        listener: Listener,
+    ) -> Result<Deck, CombatError> {
         loop {
             // first, see if we're done
             if let Some(winner) = self.winner() {
-                return Ok(winner.clone());
+                let winner = winner.player;
+                return Ok(self.game_over(winner, false, listener));
             }
 
-            // OK, let's print out some state information
-            self.round += 1;
-            println!(
-                "-- Game {}, Round {} {}--",
-                self.id,
-                self.round,
-                if recursive { "[recursive]" } else { "" }
-            );
-            for deck in self.decks.values() {
-                println!("{}", deck);
+            // if these rules stop repeated games and we've been here before,
+            // just stop.
+            let in_play: Vec<usize> = self
+                .decks
+                .values()
+                .filter(|x| x.size() > 0)
+                .map(|x| x.player)
+                .collect();
+            if let Some(winner) = self.rule.repeat_winner(&in_play) {
+                if !self.seen.insert(self.fingerprint()) {
+                    return Ok(self.game_over(winner, true, listener));
+                }
             }
 
-            // if this is the recursive version of the game and we've been here before,
-            // just stop, and player 1 won.
-            if recursive && self.history.contains(&self.decks) {
-                return self
-                    .decks
-                    .get(&1)
-                    .cloned()
-                    .ok_or(TopLevelError::UnknownError);
+            // otherwise, grab the first card off each of the decks
+            self.round += 1;
+            let mut played = Vec::new();
+            for deck in self.decks.values_mut() {
+                if let Some(top_card) = deck.top() {
+                    played.push((deck.player, top_card));
+                }
             }
+            let mut pot: Vec<usize> = played.iter().map(|x| x.1).collect();
 
-            if recursive {
-                self.history.push(self.decks.clone());
+            let subgame_sizes: Option<Vec<usize>> = played
+                .iter()
+                .map(|(player, card)| self.rule.recursion(*card, self.decks[player].size()))
+                .collect();
+            let (winner, winning_card, wars, recursion) = match subgame_sizes {
+                Some(sizes) if sizes.iter().any(|x| *x > 0) => {
+                    let (winner, recursion) = self.subgame(&played, &sizes, results, listener)?;
+                    let card = played.iter().find(|x| x.0 == winner).map_or(0, |x| x.1);
+                    (winner, card, 0, Some(recursion))
+                }
+                _ => {
+                    let (winner, card, wars) = self.showdown(played.clone(), &mut pot)?;
+                    (winner, card, wars, None)
+                }
+            };
+
+            listener(&Event::Round {
+                game: self.id,
+                depth: self.depth,
+                round: self.round,
+                played,
+                wars,
+                recursion,
+                winner,
+            });
+            if let Some(deck) = self.decks.get_mut(&winner) {
+                deck.add_cards(winning_card, &mut pot);
             }
+        }
+    }
 
-            // otherwise, grab the first card off each of the decks
-            let mut top_card_info = Vec::new();
+    /// Plays, or looks up, the sub-game started by this round's cards.
+    fn subgame(
+        &self,
+        played: &[(usize, usize)],
+        sizes: &[usize],
+        results: &mut Results,
+        listener: Listener,
+    ) -> Result<(usize, Recursion), CombatError> {
+        let new_decks: Vec<Deck> = played
+            .iter()
+            .zip(sizes.iter())
+            .map(|((player, _), size)| {
+                let mut new_deck = self.decks[player].clone();
+                new_deck.resize(*size);
+                new_deck
+            })
+            .collect();
+        let start = fingerprint(new_decks.iter());
 
-            for (player, deck) in self.decks.iter_mut() {
-                if let Some(top_card) = deck.top() {
-                    let recurse_check_value = deck.size() >= top_card;
-                    println!(
-                        "Top card for Player {}: {} [recurse check: {}]",
-                        player, top_card, recurse_check_value
-                    );
-                    top_card_info.push((deck, top_card, recurse_check_value));
+        if let Some(winner) = self
+            .rule
+            .known_winner(&new_decks)
+            .or_else(|| results.get(&start).cloned())
+        {
+            return Ok((winner, Recursion::Known));
+        }
+
+        let mut subgame = Game::new(&new_decks, self.rule);
+        subgame.depth = self.depth + 1;
+        let winner = subgame.play_with(results, listener)?.player;
+        results.insert(start, winner);
+        Ok((winner, Recursion::Played(subgame.id)))
+    }
+
+    /// Settles a round by comparing cards, going to war over ties if the
+    /// rules allow it. Returns the winner, the card they won with and how
+    /// many wars it took; every card laid down goes into `pot`.
+    fn showdown(
+        &mut self,
+        mut contenders: Vec<(usize, usize)>,
+        pot: &mut Vec<usize>,
+    ) -> Result<(usize, usize, usize), CombatError> {
+        let mut wars = 0;
+
+        loop {
+            let tied = match self.rule.judge(&contenders) {
+                Outcome::Winner(player) => {
+                    let card = contenders.iter().find(|x| x.0 == player).map_or(0, |x| x.1);
+                    return Ok((player, card, wars));
                 }
-            }
+                Outcome::Tie(tied) => tied,
+            };
+            let face_down = self
+                .rule
+                .war()
+                .ok_or_else(|| CombatError::UnsettledTie(tied.clone()))?;
+            wars += 1;
 
-            let mut top_cards = top_card_info.iter().map(|(_, x, _)| *x).collect();
-
-            // if we're in a recursive game, and we meet the length conditions, recurse
-            if recursive && top_card_info.iter().all(|(_, _, x)| *x) {
-                let new_decks: Vec<Deck> = top_card_info
-                    .iter()
-                    .map(|(deck, newlen, _)| {
-                        let mut new_deck = (&**deck).clone();
-                        new_deck.resize(*newlen);
-                        new_deck
-                    })
-                    .collect();
-                println!("Creating recursive game!");
-                let mut subgame = Game::new(&new_decks);
-                let subgame_result = subgame.play(true)?;
-
-                for (deck, top, _) in top_card_info.drain(..) {
-                    if deck.player == subgame_result.player {
-                        deck.add_cards(top, &mut top_cards);
+            let previous = contenders;
+            contenders = Vec::new();
+            for player in tied.iter() {
+                if let Some(deck) = self.decks.get_mut(player) {
+                    // always keep one back to play face up, if there's one left
+                    for _ in 0..face_down.min(deck.size().saturating_sub(1)) {
+                        pot.extend(deck.top());
+                    }
+                    if let Some(card) = deck.top() {
+                        pot.push(card);
+                        contenders.push((*player, card));
                     }
                 }
-            } else {
-                // this is just a normal case
-                let winning_card = top_card_info
-                    .iter()
-                    .map(|(_, x, _)| *x)
-                    .max()
-                    .ok_or(TopLevelError::UnknownError)?;
-                println!("The winning card is {}", winning_card);
-                let (winner, _, _) = top_card_info
-                    .drain(..)
-                    .find(|(_, x, _)| *x == winning_card)
-                    .ok_or(TopLevelError::UnknownError)?;
-                winner.add_cards(winning_card, &mut top_cards);
             }
 
-            println!();
+            // nobody had anything left to play, so the first of them takes it
+            if contenders.is_empty() {
+                let card = previous.iter().find(|x| x.0 == tied[0]).map_or(0, |x| x.1);
+                return Ok((tied[0], card, wars));
+            }
+        }
+    }
+}
+
+fn score(deck: &Deck) -> usize {
+    deck.cards
+        .iter()
+        .rev()
+        .enumerate()
+        .map(|(num, card)| (num + 1) * card)
+        .sum()
+}
+
+/// What happened over a run of games.
+#[derive(Default)]
+struct Statistics {
+    games: usize,
+    rounds: usize,
+    subgames: usize,
+    wins: BTreeMap<usize, usize>,
+    /// How many games went this many sub-games deep, at most.
+    depths: BTreeMap<usize, usize>,
+    /// The deepest sub-game so far in the game being watched.
+    deepest: usize,
+}
+
+impl Statistics {
+    /// Plays `games` games, each with the cards in `decks` shuffled and
+    /// dealt back out into decks of the same sizes.
+    fn simulate(
+        decks: &[Deck],
+        rule: &dyn RoundRule,
+        games: usize,
+        random: &mut Random,
+    ) -> Result<Statistics, CombatError> {
+        let mut cards: Vec<usize> = decks.iter().flat_map(|x| x.cards.iter().cloned()).collect();
+        let mut result = Statistics {
+            wins: decks.iter().map(|x| (x.player, 0)).collect(),
+            ..Default::default()
+        };
+
+        for _ in 0..games {
+            random.shuffle(&mut cards);
+            let mut dealt = cards.iter().cloned();
+            let deal: Vec<Deck> = decks
+                .iter()
+                .map(|x| Deck {
+                    player: x.player,
+                    cards: dealt.by_ref().take(x.size()).collect(),
+                })
+                .collect();
+            result.record(&deal, rule)?;
+        }
+
+        Ok(result)
+    }
+
+    fn record(&mut self, decks: &[Deck], rule: &dyn RoundRule) -> Result<(), CombatError> {
+        Game::new(decks, rule).play(&mut |event| self.observe(event))?;
+        Ok(())
+    }
+
+    /// Counts one event. A game is only added up once its own `GameOver`
+    /// comes along, so this works the same on a game being played and on
+    /// one read back from a log.
+    fn observe(&mut self, event: &Event) {
+        if let Event::GameOver {
+            depth,
+            rounds,
+            winner,
+            ..
+        } = event
+        {
+            self.deepest = self.deepest.max(*depth);
+            if *depth > 0 {
+                self.subgames += 1;
+                return;
+            }
+
+            self.games += 1;
+            self.rounds += rounds;
+            *self.wins.entry(*winner).or_insert(0) += 1;
+            *self.depths.entry(self.deepest).or_insert(0) += 1;
+            self.deepest = 0;
         }
     }
 }
 
+impl fmt::Display for Statistics {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let games = self.games.max(1) as f64;
+
+        writeln!(f, "Games played: {}", self.games)?;
+        for (player, wins) in self.wins.iter() {
+            writeln!(
+                f,
+                "Player {} won {} ({:.1}%)",
+                player,
+                wins,
+                100.0 * (*wins as f64) / games
+            )?;
+        }
+        writeln!(
+            f,
+            "Average game length: {:.1} rounds",
+            self.rounds as f64 / games
+        )?;
+        writeln!(
+            f,
+            "Average sub-games played: {:.1}",
+            self.subgames as f64 / games
+        )?;
+        writeln!(f, "Deepest sub-game reached:")?;
+        for (depth, count) in self.depths.iter() {
+            writeln!(
+                f,
+                "  depth {}: {} ({:.1}%)",
+                depth,
+                count,
+                100.0 * (*count as f64) / games
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+fn read_decks_from(filename: &str) -> Vec<Deck> {
+    read_decks(&fs::read_to_string(filename).unwrap()).unwrap()
+}
+
+#[cfg(test)]
+fn play(decks: &str, rule: &dyn RoundRule) -> Result<Deck, CombatError> {
+    Game::new(&read_decks(decks).unwrap(), rule).play(&mut |_| {})
+}
+
+#[cfg(test)]
+fn events(decks: &[Deck], rule: &dyn RoundRule) -> Vec<Event> {
+    let mut result = Vec::new();
+    Game::new(decks, rule)
+        .play(&mut |x| result.push(x.clone()))
+        .unwrap();
+    result
+}
+
+#[test]
+fn recursive_example() {
+    let decks = read_decks_from("inputs/day22_test.txt");
+    let mut quiet = |_: &Event| {};
+
+    let normal = Game::new(&decks, &HighestCard).play(&mut quiet).unwrap();
+    assert_eq!(306, score(&normal));
+    let standard = Recursive::standard();
+    let mut results = Results::new();
+    let recursive = Game::new(&decks, &standard)
+        .play_with(&mut results, &mut quiet)
+        .unwrap();
+    assert_eq!(2, recursive.player);
+    assert_eq!(291, score(&recursive));
+    assert!(!results.is_empty());
+
+    let twins = read_decks("Player 1:\n9\n1\n\nPlayer 2:\n9\n2\n").unwrap();
+    assert_eq!(None, standard.known_winner(&twins));
+    let distinct = read_decks("Player 1:\n9\n1\n\nPlayer 2:\n3\n2\n").unwrap();
+    assert_eq!(Some(1), standard.known_winner(&distinct));
+
+    let halting = read_decks_from("inputs/day22_test_halt.txt");
+    assert_eq!(
+        1,
+        Game::new(&halting, &standard)
+            .play(&mut quiet)
+            .unwrap()
+            .player
+    );
+
+    // a trigger that never fires is just the normal game
+    let never = Recursive::with_trigger(HighestCard, |_, _| None);
+    assert_eq!(
+        306,
+        score(&Game::new(&decks, &never).play(&mut quiet).unwrap())
+    );
+}
+
+#[test]
+fn other_rules() {
+    let three = "Player 1:\n5\n1\n\nPlayer 2:\n4\n2\n\nPlayer 3:\n6\n3\n";
+
+    let highest = play(three, &HighestCard).unwrap();
+    assert_eq!(3, highest.player);
+    assert_eq!(vec![6, 5, 4, 3, 2, 1], Vec::from(highest.cards));
+
+    let lowest = play(three, &LowestCard).unwrap();
+    assert_eq!(1, lowest.player);
+    assert_eq!(vec![1, 4, 3, 6, 2, 5], Vec::from(lowest.cards));
+
+    let tie = "Player 1:\n5\n9\n1\n2\n\nPlayer 2:\n5\n8\n7\n3\n";
+    assert_eq!(
+        Err(CombatError::UnsettledTie(vec![1, 2])),
+        play(tie, &HighestCard)
+    );
+    let war = play(
+        tie,
+        &War {
+            base: HighestCard,
+            face_down: 1,
+        },
+    )
+    .unwrap();
+    assert_eq!(2, war.player);
+    assert_eq!(vec![7, 9, 8, 5, 5, 1, 3, 2], Vec::from(war.cards));
+
+    assert_eq!(
+        Err(CombatError::NotEnoughPlayers(1)),
+        read_decks("Player 1:\n1\n")
+    );
+    assert_eq!(
+        Err(CombatError::DuplicatePlayer(1)),
+        read_decks("Player 1:\n1\n\nPlayer 1:\n2\n")
+    );
+}
+
+#[test]
+fn event_log() {
+    let decks = read_decks_from("inputs/day22_test.txt");
+    let log = events(&decks, &Recursive::standard());
+
+    match log.first() {
+        Some(Event::Round {
+            round,
+            played,
+            depth,
+            recursion,
+            winner,
+            ..
+        }) => {
+            assert_eq!((1, 0, 1), (*round, *depth, *winner));
+            assert_eq!(&vec![(1, 9), (2, 5)], played);
+            assert_eq!(&None, recursion);
+        }
+        other => panic!("unexpected first event {:?}", other),
+    }
+    match log.last() {
+        Some(Event::GameOver {
+            depth,
+            rounds,
+            winner,
+            repeated,
+            ..
+        }) => assert_eq!((0, 17, 2, false), (*depth, *rounds, *winner, *repeated)),
+        other => panic!("unexpected last event {:?}", other),
+    }
+    // the sub-game's events come before the round that started it
+    let subgame = log
+        .iter()
+        .find_map(|x| match x {
+            Event::Round {
+                recursion: Some(Recursion::Played(id)),
+                ..
+            } => Some(*id),
+            _ => None,
+        })
+        .unwrap();
+    let over = log
+        .iter()
+        .position(|x| matches!(x, Event::GameOver { game, depth: 1, .. } if *game == subgame))
+        .unwrap();
+    let started = log
+        .iter()
+        .position(|x| matches!(x, Event::Round { recursion: Some(Recursion::Played(id)), .. } if *id == subgame))
+        .unwrap();
+    assert!(over < started);
+
+    for event in log.iter() {
+        assert_eq!(Ok(event.clone()), Event::from_str(&event.to_string()));
+    }
+    assert_eq!(
+        Err(CombatError::BadEvent(
+            "Game 1 (depth 0), round 1".to_string()
+        )),
+        Event::from_str("Game 1 (depth 0), round 1")
+    );
+
+    let halting = read_decks_from("inputs/day22_test_halt.txt");
+    assert!(matches!(
+        events(&halting, &Recursive::standard()).last(),
+        Some(Event::GameOver {
+            winner: 1,
+            repeated: true,
+            ..
+        })
+    ));
+}
+
+#[test]
+fn statistics() {
+    let decks = read_decks_from("inputs/day22_test.txt");
+    let stats =
+        Statistics::simulate(&decks, &Recursive::standard(), 50, &mut Random::new(1)).unwrap();
+
+    assert_eq!(50, stats.games);
+    assert_eq!(50, stats.wins.values().sum::<usize>());
+    assert_eq!(50, stats.depths.values().sum::<usize>());
+    assert!(stats.rounds > 0);
+
+    let again =
+        Statistics::simulate(&decks, &Recursive::standard(), 50, &mut Random::new(1)).unwrap();
+    assert_eq!(stats.wins, again.wins);
+    assert_eq!(stats.depths, again.depths);
+
+    // replaying a logged game counts the same as playing it
+    let mut played = Statistics::default();
+    played.record(&decks, &Recursive::standard()).unwrap();
+    let mut replayed = Statistics::default();
+    for event in events(&decks, &Recursive::standard()) {
+        replayed.observe(&Event::from_str(&event.to_string()).unwrap());
+    }
+    assert_eq!(
+        (played.games, played.rounds, played.subgames),
+        (replayed.games, replayed.rounds, replayed.subgames)
+    );
+    assert_eq!(played.wins, replayed.wins);
+    assert_eq!(played.depths, replayed.depths);
+}
+
 fn main() -> Result<(), TopLevelError> {
     let filename = env::args().nth(1).expect("No file argument given.");
     let contents = fs::read_to_string(filename)?;
-    let mut lines = contents.lines();
-    let deck1 = Deck::read(&mut lines)?;
-    let deck2 = Deck::read(&mut lines)?;
-    let decks = [deck1, deck2];
-    let mut game1 = Game::new(&decks);
+    let args: Vec<String> = env::args().skip(2).collect();
+
+    // a log written in verbose mode can be read back and summed up
+    if args.first().map(|x| x.as_str()) == Some("replay") {
+        let mut statistics = Statistics::default();
+        for line in contents.lines().filter(|x| x.starts_with("Game ")) {
+            statistics.observe(&Event::from_str(line)?);
+        }
+        print!("{}", statistics);
+        return Ok(());
+    }
+
+    let decks = read_decks(&contents)?;
 
-    let result = game1.play(true)?;
-    let mut sum = 0;
+    // either 'stats GAMES', then optionally a rule name and a seed, or an
+    // optional rule name and then optionally 'verbose'
+    let mut rule_name = "recursive";
+    let mut verbose = false;
+    let mut simulation = None;
+    let mut seed = 1;
+    let mut rest = args.iter().map(|x| x.as_str());
+    if args.first().map(|x| x.as_str()) == Some("stats") {
+        rest.next();
+        match rest.next().map(usize::from_str) {
+            Some(Ok(games)) => simulation = Some(games),
+            _ => {
+                return Err(TopLevelError::BadUsage(
+                    "how many games should the statistics cover?".to_string(),
+                ))
+            }
+        }
+    }
+    for arg in rest {
+        if arg == "verbose" {
+            verbose = true;
+        } else if let (Some(_), Ok(value)) = (simulation, u64::from_str(arg)) {
+            seed = value;
+        } else {
+            rule_name = arg;
+        }
+    }
+    let rule = named_rule(rule_name).ok_or_else(|| {
+        TopLevelError::BadUsage(format!("unknown rule {}; try {}", rule_name, RULES))
+    })?;
 
-    for (num, card) in result.cards.iter().rev().enumerate() {
-        sum += (num + 1) * card;
+    if let Some(games) = simulation {
+        let mut random = Random::new(seed);
+        print!(
+            "{}",
+            Statistics::simulate(&decks, rule.as_ref(), games, &mut random)?
+        );
+        return Ok(());
     }
 
+    let mut game1 = Game::new(&decks, rule.as_ref());
+    let mut listener = |event: &Event| {
+        if verbose {
+            println!("{}", event);
+        }
+    };
+    let result = game1.play(&mut listener)?;
+
     println!("Winning deck: {}", result);
-    println!("Final puzzle result: {}", sum);
+    println!("Final puzzle result: {}", score(&result));
 
     Ok(())
 }
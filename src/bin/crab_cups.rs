@@ -1,191 +1,615 @@
-use advent2020::errors::TopLevelError;
+use advent2020::errors::{CupError, TopLevelError};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::env;
 use std::fmt;
-use std::time::SystemTime;
+use std::fs::{self, File};
+use std::hash::{Hash, Hasher};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Instant;
 
-#[derive(Clone)]
-struct CupSlot {
-    value: usize,
-    next: usize,
+/// How to find the next cup to try as the destination. Cups are ranked by
+/// where their label comes in sorted order, so `step` turns one rank into
+/// the next, out of `cups` ranks.
+trait DestinationRule {
+    fn step(&self, rank: usize, cups: usize) -> usize;
+
+    /// What `named_rule` knows this rule as.
+    fn name(&self) -> String;
+}
+
+/// The puzzle's rule: the next label down, wrapping around to the top.
+struct MinusOne;
+
+impl DestinationRule for MinusOne {
+    fn step(&self, rank: usize, cups: usize) -> usize {
+        (rank + cups - 1) % cups
+    }
+
+    fn name(&self) -> String {
+        "minus-one".to_string()
+    }
 }
 
-impl CupSlot {
-    fn blank() -> CupSlot {
-        CupSlot { value: 0, next: 0 }
+/// The next label up, wrapping around to the bottom.
+struct PlusOne;
+
+impl DestinationRule for PlusOne {
+    fn step(&self, rank: usize, cups: usize) -> usize {
+        (rank + 1) % cups
+    }
+
+    fn name(&self) -> String {
+        "plus-one".to_string()
+    }
+}
+
+/// Count this many labels down each time, wrapping around.
+struct Skip(usize);
+
+impl DestinationRule for Skip {
+    fn step(&self, rank: usize, cups: usize) -> usize {
+        (rank + cups - (self.0 % cups)) % cups
     }
+
+    fn name(&self) -> String {
+        format!("skip-{}", self.0)
+    }
+}
+
+impl<R: DestinationRule + ?Sized> DestinationRule for Box<R> {
+    fn step(&self, rank: usize, cups: usize) -> usize {
+        (**self).step(rank, cups)
+    }
+
+    fn name(&self) -> String {
+        (**self).name()
+    }
+}
+
+const RULES: &str = "minus-one, plus-one or skip-K";
+
+fn named_rule(name: &str) -> Option<Box<dyn DestinationRule>> {
+    match name {
+        "minus-one" => Some(Box::new(MinusOne)),
+        "plus-one" => Some(Box::new(PlusOne)),
+        _ => {
+            let skip = name.strip_prefix("skip-")?.parse().ok()?;
+            Some(Box::new(Skip(skip)))
+        }
+    }
+}
+
+const CHECKPOINT_MAGIC: &[u8; 4] = b"CUP2";
+
+fn read_exactly<Rd: Read>(input: &mut Rd, buffer: &mut [u8]) -> Result<(), CupError> {
+    input
+        .read_exact(buffer)
+        .map_err(|x| CupError::BadCheckpoint(x.to_string()))
+}
+
+/// What a long run does along the way.
+struct RunOptions {
+    /// Where to save a checkpoint, and how many rounds apart.
+    checkpoint: Option<(PathBuf, usize)>,
+    /// How many rounds apart to report progress on stderr, or 0 not to.
+    report_every: usize,
 }
 
-struct CupGame {
-    // this is a slightly wacky encoding; element 0 tells you which number
-    // comes first, the rest of the elements tell you which number comes
-    // after the indexed one. So mappings[1] tells you which number comes
-    // after the element 1 in the list.
-    nodes: Vec<CupSlot>,
-    value_indices: Vec<usize>,
+struct CupGame<L, R> {
+    // Cups are numbered by their rank, and next[n] is the number of the cup
+    // clockwise from cup n; labels[n] is its label. So the whole circle is
+    // one flat array, whatever the labels are.
+    labels: Vec<L>,
+    ranks: HashMap<L, usize>,
+    next: Vec<usize>,
+    pick_up: usize,
+    pulled: Vec<usize>,
+    rule: R,
     round: usize,
     current_cup: usize,
-    maximum_value: usize,
-}
-
-impl CupGame {
-    fn new(problem_size: usize, initial_cups: &[usize]) -> CupGame {
-        let mut nodes = vec![CupSlot::blank(); problem_size + 1];
-        let mut value_indices = vec![0; problem_size + 1];
-        let mut previous = 0;
-        let mut current = 1;
-        let mut maximum_value = 0;
-
-        for value in initial_cups.iter() {
-            nodes[current].value = *value;
-            value_indices[*value] = current;
-            nodes[previous].next = current;
-            if value > &maximum_value {
-                maximum_value = *value;
-            }
-            previous = current;
-            current += 1;
+}
+
+impl<L: Clone + Hash + Ord + fmt::Display, R: DestinationRule> CupGame<L, R> {
+    /// Puts `cups` out in a circle, in order, with the first one current.
+    fn new(cups: &[L], pick_up: usize, rule: R) -> Result<CupGame<L, R>, CupError> {
+        if cups.len() < pick_up + 2 {
+            return Err(CupError::TooFewCups(cups.len(), pick_up));
         }
 
-        while current <= problem_size {
-            maximum_value += 1;
-            value_indices[maximum_value] = current;
-            nodes[previous].next = current;
-            nodes[current].value = maximum_value;
-            previous = current;
-            current += 1;
+        let mut labels = cups.to_vec();
+        labels.sort();
+        if let Some(pair) = labels.windows(2).find(|x| x[0] == x[1]) {
+            return Err(CupError::DuplicateLabel(pair[0].to_string()));
         }
+        let ranks: HashMap<L, usize> = labels
+            .iter()
+            .cloned()
+            .enumerate()
+            .map(|(rank, label)| (label, rank))
+            .collect();
 
-        nodes[previous].next = 1;
+        let order: Vec<usize> = cups.iter().map(|x| ranks[x]).collect();
+        let mut next = vec![0; order.len()];
+        for (idx, rank) in order.iter().enumerate() {
+            next[*rank] = order[(idx + 1) % order.len()];
+        }
 
-        CupGame {
-            nodes,
-            value_indices,
-            maximum_value,
+        Ok(CupGame {
+            labels,
+            ranks,
+            next,
+            pick_up,
+            pulled: Vec::with_capacity(pick_up),
+            rule,
             round: 1,
-            current_cup: 1,
-        }
+            current_cup: order[0],
+        })
     }
 
-    fn pull_next(&mut self) -> (usize, usize, usize) {
-        let a_idx = self.nodes[self.current_cup].next;
-        let b_idx = self.nodes[a_idx].next;
-        let c_idx = self.nodes[b_idx].next;
+    fn pull_next(&mut self) {
+        let mut idx = self.current_cup;
 
-        self.nodes[self.current_cup].next = self.nodes[c_idx].next;
+        self.pulled.clear();
+        for _ in 0..self.pick_up {
+            idx = self.next[idx];
+            self.pulled.push(idx);
+        }
 
-        (a_idx, b_idx, c_idx)
+        self.next[self.current_cup] = self.next[idx];
     }
 
-    fn destination_cup(&self, pulled: &(usize, usize, usize)) -> usize {
-        let mut proposed_value = self.nodes[self.current_cup].value;
-        let (a_idx, b_idx, c_idx) = pulled;
-        let a = self.nodes[*a_idx].value;
-        let b = self.nodes[*b_idx].value;
-        let c = self.nodes[*c_idx].value;
+    fn destination_cup(&self) -> Option<usize> {
+        let cups = self.next.len();
+        let mut proposed = self.current_cup;
 
-        loop {
-            if proposed_value == 1 {
-                proposed_value = self.maximum_value;
-            } else {
-                proposed_value -= 1;
+        for _ in 0..cups {
+            proposed = self.rule.step(proposed, cups);
+            if proposed != self.current_cup && !self.pulled.contains(&proposed) {
+                return Some(proposed);
             }
+        }
 
-            if (proposed_value != a) && (proposed_value != b) && (proposed_value != c) {
-                return proposed_value;
-            }
+        None
+    }
+
+    fn reinject(&mut self, at: usize) {
+        if let (Some(first), Some(last)) = (self.pulled.first(), self.pulled.last()) {
+            self.next[*last] = self.next[at];
+            self.next[at] = *first;
         }
     }
 
-    fn reinject(&mut self, at_value: usize, pulled: (usize, usize, usize)) {
-        let (a_idx, b_idx, c_idx) = pulled;
-        let idx = self.value_indices[at_value];
+    fn run_round(&mut self) -> Result<(), CupError> {
+        self.pull_next();
+        let destination_cup = self
+            .destination_cup()
+            .ok_or(CupError::NoDestination(self.round))?;
+        self.reinject(destination_cup);
+        self.current_cup = self.next[self.current_cup];
+        self.round += 1;
+        Ok(())
+    }
 
-        self.nodes[a_idx].next = b_idx;
-        self.nodes[b_idx].next = c_idx;
-        self.nodes[c_idx].next = self.nodes[idx].next;
-        self.nodes[idx].next = a_idx;
+    /// A hash of every label, so a checkpoint can tell if it's being
+    /// restored into a game with different cups.
+    fn labels_hash(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        self.labels.hash(&mut hasher);
+        hasher.finish()
     }
 
-    fn run_round(&mut self) {
-        let pull = self.pull_next();
-        let destination_cup = self.destination_cup(&pull);
-        self.reinject(destination_cup, pull);
-        self.current_cup = self.nodes[self.current_cup].next;
-        self.round += 1;
+    /// Writes the circle, the current cup and the round out as a little
+    /// endian header, then the rule's name and then one `u32` per cup. The
+    /// labels themselves aren't saved, only a hash of them, so a checkpoint
+    /// can only be restored into a game set up the same way.
+    fn write_checkpoint<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        if self.next.len() > u32::MAX as usize {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} cups is too many to checkpoint", self.next.len()),
+            ));
+        }
+
+        let name = self.rule.name();
+        out.write_all(CHECKPOINT_MAGIC)?;
+        for value in [
+            self.next.len() as u64,
+            self.pick_up as u64,
+            self.round as u64,
+            self.current_cup as u64,
+            self.labels_hash(),
+            name.len() as u64,
+        ]
+        .iter()
+        {
+            out.write_all(&value.to_le_bytes())?;
+        }
+        out.write_all(name.as_bytes())?;
+        for next in self.next.iter() {
+            out.write_all(&(*next as u32).to_le_bytes())?;
+        }
+        Ok(())
+    }
+
+    fn restore<Rd: Read>(&mut self, input: &mut Rd) -> Result<(), CupError> {
+        let mut magic = [0; 4];
+        read_exactly(input, &mut magic)?;
+        if &magic != CHECKPOINT_MAGIC {
+            return Err(CupError::BadCheckpoint("not a cup checkpoint".to_string()));
+        }
+
+        let mut header = [0; 6];
+        for value in header.iter_mut() {
+            let mut bytes = [0; 8];
+            read_exactly(input, &mut bytes)?;
+            *value = u64::from_le_bytes(bytes);
+        }
+        let [cups, pick_up, round, current_cup, labels_hash, name_length] = header;
+        let (cups, pick_up, round, current_cup) = (
+            cups as usize,
+            pick_up as usize,
+            round as usize,
+            current_cup as usize,
+        );
+        if cups != self.next.len() || pick_up != self.pick_up {
+            return Err(CupError::BadCheckpoint(format!(
+                "saved with {} cups picking up {}, not {} picking up {}",
+                cups,
+                pick_up,
+                self.next.len(),
+                self.pick_up
+            )));
+        }
+        if labels_hash != self.labels_hash() {
+            return Err(CupError::BadCheckpoint(
+                "saved with differently labelled cups".to_string(),
+            ));
+        }
+        let mut name = vec![0; (name_length as usize).min(64)];
+        read_exactly(input, &mut name)?;
+        if name_length as usize != name.len() || name != self.rule.name().as_bytes() {
+            return Err(CupError::BadCheckpoint(format!(
+                "saved with rule {}, not {}",
+                String::from_utf8_lossy(&name),
+                self.rule.name()
+            )));
+        }
+
+        let mut bytes = vec![0; cups * 4];
+        read_exactly(input, &mut bytes)?;
+        let mut seen = vec![false; cups];
+        let mut next = Vec::with_capacity(cups);
+        for chunk in bytes.chunks_exact(4) {
+            let cup = u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]) as usize;
+            if cup >= cups || seen[cup] {
+                return Err(CupError::BadCheckpoint(format!(
+                    "cup {} is out of place",
+                    cup
+                )));
+            }
+            seen[cup] = true;
+            next.push(cup);
+        }
+        if current_cup >= cups {
+            return Err(CupError::BadCheckpoint(format!(
+                "current cup {} doesn't exist",
+                current_cup
+            )));
+        }
+
+        // every cup is next to exactly one other, but that could still be
+        // several separate circles
+        let mut circle = 1;
+        let mut cup = next[current_cup];
+        while cup != current_cup {
+            circle += 1;
+            cup = next[cup];
+        }
+        if circle != cups {
+            return Err(CupError::BadCheckpoint(format!(
+                "only {} of the {} cups are in the circle",
+                circle, cups
+            )));
+        }
+
+        self.next = next;
+        self.round = round;
+        self.current_cup = current_cup;
+        Ok(())
     }
 
-    fn part1_answer(&self) -> String {
-        let idx = self.value_indices[1];
-        let mut retval = String::new();
-        let mut work = self.nodes[idx].next;
+    /// Plays until round `last_round` is done, checkpointing and reporting
+    /// progress as `options` asks.
+    fn run_until(&mut self, last_round: usize, options: &RunOptions) -> Result<(), TopLevelError> {
+        let start_time = Instant::now();
+        let start_round = self.round;
+        let mut last_report = start_time;
 
-        while self.nodes[work].value != 1 {
-            retval.push_str(format!("{}", self.nodes[work].value).as_str());
-            work = self.nodes[work].next;
+        while self.round <= last_round {
+            self.run_round()?;
+
+            let played = self.round - 1;
+            if let Some((path, every)) = &options.checkpoint {
+                if played.is_multiple_of(*every) {
+                    self.save(path)?;
+                }
+            }
+            if options.report_every > 0 && played.is_multiple_of(options.report_every) {
+                let now = Instant::now();
+                let rate = options.report_every as f64
+                    / now.duration_since(last_report).as_secs_f64().max(1e-9);
+                let overall = (self.round - start_round) as f64
+                    / now.duration_since(start_time).as_secs_f64().max(1e-9);
+                let left = ((last_round - played) as f64 / overall) as u64;
+                eprintln!(
+                    "Round {}/{}: {:.0} rounds/s, about {}m{}s to go",
+                    played,
+                    last_round,
+                    rate,
+                    left / 60,
+                    left % 60
+                );
+                last_report = now;
+            }
         }
 
-        retval
+        Ok(())
     }
 
-    fn part2_answer(&self) -> usize {
-        let idx = self.value_indices[1];
-        let a_idx = self.nodes[idx].next;
-        let b_idx = self.nodes[a_idx].next;
-        let a = self.nodes[a_idx].value;
-        let b = self.nodes[b_idx].value;
-        println!("{} * {} = {}", a, b, a * b);
-        a * b
+    /// Writes a checkpoint next to `path` and then moves it into place, so
+    /// an interruption can't leave half a checkpoint behind.
+    fn save(&self, path: &Path) -> io::Result<()> {
+        let mut temporary = path.as_os_str().to_owned();
+        temporary.push(".tmp");
+        let mut out = BufWriter::new(File::create(&temporary)?);
+        self.write_checkpoint(&mut out)?;
+        out.flush()?;
+        drop(out);
+        fs::rename(&temporary, path)
+    }
+
+    /// The labels of the `count` cups clockwise from the one labelled `label`.
+    fn following(&self, label: &L, count: usize) -> Result<Vec<&L>, CupError> {
+        let mut work = *self
+            .ranks
+            .get(label)
+            .ok_or_else(|| CupError::UnknownLabel(label.to_string()))?;
+        let mut result = Vec::with_capacity(count);
+
+        for _ in 0..count {
+            work = self.next[work];
+            result.push(&self.labels[work]);
+        }
+
+        Ok(result)
     }
 }
 
-impl fmt::Display for CupGame {
+impl<L: fmt::Display, R> fmt::Display for CupGame<L, R> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "Round {}: ", self.round)?;
         let mut idx = self.current_cup;
-        let first_value = self.nodes[idx].value;
 
-        write!(f, "({}) ", first_value)?;
-        idx = self.nodes[idx].next;
-        while self.nodes[idx].value != first_value {
-            write!(f, "{} ", self.nodes[idx].value)?;
-            idx = self.nodes[idx].next;
+        write!(f, "({}) ", self.labels[idx])?;
+        idx = self.next[idx];
+        while idx != self.current_cup {
+            write!(f, "{} ", self.labels[idx])?;
+            idx = self.next[idx];
         }
 
         Ok(())
     }
 }
 
+fn part1_answer<R: DestinationRule>(game: &CupGame<usize, R>) -> Result<String, CupError> {
+    Ok(game
+        .following(&1, game.labels.len() - 1)?
+        .iter()
+        .map(|x| x.to_string())
+        .collect())
+}
+
+fn part2_answer<R: DestinationRule>(game: &CupGame<usize, R>) -> Result<usize, CupError> {
+    let next = game.following(&1, 2)?;
+    println!("{} * {} = {}", next[0], next[1], next[0] * next[1]);
+    Ok(next[0] * next[1])
+}
+
+#[cfg(test)]
+fn digits(s: &str) -> Vec<usize> {
+    s.chars()
+        .map(|x| x.to_digit(10).unwrap() as usize)
+        .collect()
+}
+
+#[test]
+fn example() {
+    let mut game = CupGame::new(&digits("389125467"), 3, MinusOne).unwrap();
+    for _ in 0..10 {
+        game.run_round().unwrap();
+    }
+    assert_eq!("92658374", part1_answer(&game).unwrap());
+    for _ in 10..100 {
+        game.run_round().unwrap();
+    }
+    assert_eq!("67384529", part1_answer(&game).unwrap());
+}
+
+#[test]
+fn variants() {
+    // letters in place of digits play out exactly the same
+    let letters: Vec<char> = "chiabedfg".chars().collect();
+    let mut game = CupGame::new(&letters, 3, MinusOne).unwrap();
+    for _ in 0..10 {
+        game.run_round().unwrap();
+    }
+    let after: String = game.following(&'a', 8).unwrap().into_iter().collect();
+    assert_eq!("ibfehcgd", after);
+
+    // looking upwards instead
+    let mut game = CupGame::new(&digits("12345"), 1, PlusOne).unwrap();
+    game.run_round().unwrap();
+    assert_eq!("Round 2: (3) 2 4 5 1 ", game.to_string());
+
+    assert!(matches!(
+        CupGame::new(&digits("1223"), 1, MinusOne),
+        Err(CupError::DuplicateLabel(x)) if x == "2"
+    ));
+    assert!(matches!(
+        CupGame::new(&digits("1234"), 3, MinusOne),
+        Err(CupError::TooFewCups(4, 3))
+    ));
+    let mut stuck = CupGame::new(&digits("1324"), 1, Skip(2)).unwrap();
+    assert_eq!(Err(CupError::NoDestination(1)), stuck.run_round());
+    assert!(matches!(
+        stuck.following(&7, 1),
+        Err(CupError::UnknownLabel(x)) if x == "7"
+    ));
+}
+
+#[test]
+fn checkpoints() {
+    let cups = digits("389125467");
+    let mut game = CupGame::new(&cups, 3, MinusOne).unwrap();
+    for _ in 0..50 {
+        game.run_round().unwrap();
+    }
+    let mut saved = Vec::new();
+    game.write_checkpoint(&mut saved).unwrap();
+    assert_eq!(4 + 48 + 9 + 36, saved.len());
+
+    let mut resumed = CupGame::new(&cups, 3, MinusOne).unwrap();
+    resumed.restore(&mut saved.as_slice()).unwrap();
+    assert_eq!(game.to_string(), resumed.to_string());
+    let options = RunOptions {
+        checkpoint: None,
+        report_every: 0,
+    };
+    game.run_until(100, &options).unwrap();
+    resumed.run_until(100, &options).unwrap();
+    assert_eq!("67384529", part1_answer(&resumed).unwrap());
+    assert_eq!(game.to_string(), resumed.to_string());
+
+    let path = env::temp_dir().join(format!("crab_cups_{}.checkpoint", std::process::id()));
+    let mut saving = CupGame::new(&cups, 3, MinusOne).unwrap();
+    let options = RunOptions {
+        checkpoint: Some((path.clone(), 30)),
+        report_every: 0,
+    };
+    saving.run_until(100, &options).unwrap();
+    let mut reloaded = CupGame::new(&cups, 3, MinusOne).unwrap();
+    reloaded
+        .restore(&mut BufReader::new(File::open(&path).unwrap()))
+        .unwrap();
+    fs::remove_file(&path).unwrap();
+    assert_eq!(91, reloaded.round);
+
+    let mut other = CupGame::new(&cups, 2, MinusOne).unwrap();
+    assert!(matches!(
+        other.restore(&mut saved.as_slice()),
+        Err(CupError::BadCheckpoint(_))
+    ));
+    let mut other_rule = CupGame::new(&cups, 3, PlusOne).unwrap();
+    assert!(matches!(
+        other_rule.restore(&mut saved.as_slice()),
+        Err(CupError::BadCheckpoint(x)) if x.contains("rule")
+    ));
+    let mut other_labels = CupGame::new(&digits("389125460"), 3, MinusOne).unwrap();
+    assert!(matches!(
+        other_labels.restore(&mut saved.as_slice()),
+        Err(CupError::BadCheckpoint(x)) if x.contains("labelled")
+    ));
+    // swapping where two cups point splits the circle in two
+    let mut split = saved.clone();
+    for idx in 0..4 {
+        split.swap(61 + idx, 65 + idx);
+    }
+    assert!(matches!(
+        resumed.restore(&mut split.as_slice()),
+        Err(CupError::BadCheckpoint(x)) if x.contains("circle")
+    ));
+    assert!(matches!(
+        resumed.restore(&mut &saved[..40]),
+        Err(CupError::BadCheckpoint(_))
+    ));
+    saved[0] = b'X';
+    assert!(matches!(
+        resumed.restore(&mut saved.as_slice()),
+        Err(CupError::BadCheckpoint(_))
+    ));
+}
+
 fn main() -> Result<(), TopLevelError> {
-    let initial_cups = &[3, 6, 8, 1, 9, 5, 7, 4, 2];
-    let mut game = CupGame::new(9, initial_cups);
+    // optionally the starting cups, how many to pick up and a rule name,
+    // then any of 'rounds N', 'checkpoint FILE', 'every N' and 'report N'
+    let args: Vec<String> = env::args().skip(1).collect();
+    let keywords = ["rounds", "checkpoint", "every", "report"];
+    let split = args
+        .iter()
+        .position(|x| keywords.contains(&x.as_str()))
+        .unwrap_or(args.len());
+    let (positional, options) = args.split_at(split);
+
+    let initial_cups: Vec<usize> = match positional.first() {
+        Some(cups) => cups
+            .chars()
+            .map(|x| x.to_digit(10).map(|x| x as usize))
+            .collect::<Option<Vec<usize>>>()
+            .ok_or(TopLevelError::NoInputFound)?,
+        None => vec![3, 6, 8, 1, 9, 5, 7, 4, 2],
+    };
+    let pick_up = match positional.get(1) {
+        Some(count) => usize::from_str(count)?,
+        None => 3,
+    };
+    let rule_name = positional.get(2).map(|x| x.as_str()).unwrap_or("minus-one");
+    let rule = named_rule(rule_name).ok_or_else(|| {
+        TopLevelError::BadUsage(format!("unknown rule {}; try {}", rule_name, RULES))
+    })?;
+
+    let mut rounds = 10_000_000;
+    let mut checkpoint = None;
+    let mut every = 1_000_000;
+    let mut report_every = 1_000_000;
+    for pair in options.chunks(2) {
+        match pair {
+            [key, value] if key == "checkpoint" => checkpoint = Some(PathBuf::from(value)),
+            [key, value] if key == "rounds" => rounds = usize::from_str(value)?,
+            [key, value] if key == "every" => every = usize::from_str(value)?.max(1),
+            [key, value] if key == "report" => report_every = usize::from_str(value)?,
+            _ => {
+                return Err(TopLevelError::BadUsage(
+                    "options come in pairs: rounds N, checkpoint FILE, every N or report N"
+                        .to_string(),
+                ))
+            }
+        }
+    }
+
+    let mut game = CupGame::new(&initial_cups, pick_up, rule)?;
 
     for _ in 0..100 {
         println!("{}", game);
-        game.run_round();
-    }
-    println!("Part 1 answer: {:?}", game.part1_answer());
-
-    let start_time = SystemTime::now();
-    let mut last_chunk = start_time;
-    let mut game2 = CupGame::new(1_000_000, initial_cups);
-    for i in 0..10_000_000 - 100 {
-        if i % 1_000_000 == 0 {
-            let now = SystemTime::now();
-            let since_start = now.duration_since(start_time).unwrap().as_secs();
-            let since_last = now.duration_since(last_chunk).unwrap().as_secs();
-
-            println!(
-                "Round {}: {}m{}s since start, {}m{}s for this chunk",
-                i,
-                since_start / 60,
-                since_start % 60,
-                since_last / 60,
-                since_last % 60
-            );
-            last_chunk = now;
-        }
-        game2.run_round();
-    }
-    println!("Part 2 answer: {}", game2.part2_answer());
+        game.run_round()?;
+    }
+    println!("Part 1 answer: {:?}", part1_answer(&game)?);
+
+    let mut cups = initial_cups.clone();
+    cups.extend(initial_cups.iter().max().map_or(1, |x| x + 1)..=1_000_000);
+    let mut game2 = CupGame::new(&cups, pick_up, game.rule)?;
+    if let Some(path) = checkpoint.as_ref().filter(|x| x.exists()) {
+        game2.restore(&mut BufReader::new(File::open(path)?))?;
+        eprintln!("Resuming from round {}", game2.round);
+    }
+    let options = RunOptions {
+        checkpoint: checkpoint.map(|x| (x, every)),
+        report_every,
+    };
+    game2.run_until(rounds, &options)?;
+    println!("Part 2 answer: {}", part2_answer(&game2)?);
 
     Ok(())
 }
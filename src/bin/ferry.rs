@@ -30,9 +30,9 @@ impl TryFrom<char> for FerryLocation {
     }
 }
 
-impl Into<char> for FerryLocation {
-    fn into(self) -> char {
-        match self {
+impl From<FerryLocation> for char {
+    fn from(val: FerryLocation) -> Self {
+        match val {
             FerryLocation::Floor => '.',
             FerryLocation::EmptySeat => 'L',
             FerryLocation::TakenSeat => '#',
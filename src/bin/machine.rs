@@ -1,173 +1,103 @@
-use advent2020::errors::{ExecutionError, InstructionParseError, TopLevelError};
-use std::fmt;
-use std::fs;
+use advent2020::errors::TopLevelError;
+use advent2020::machine::{assemble, disassemble, DebugCommand, Debugger, Machine, ACCUMULATOR};
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufWriter, Write};
 use std::str::FromStr;
-use std::{collections::HashSet, env};
 
-#[derive(Clone)]
-struct Machine {
-    instructions: Vec<Instruction>,
-    accumulator: isize,
-    location: isize,
-}
-
-#[derive(Clone)]
-enum Instruction {
-    NOP(isize),
-    ACC(isize),
-    JMP(isize),
-}
-
-impl FromStr for Instruction {
-    type Err = InstructionParseError;
-
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let mut lowered = s.to_string();
-
-        lowered.make_ascii_lowercase();
+fn debug(machine: Machine) -> Result<(), TopLevelError> {
+    let mut debugger = Debugger::new(machine);
+    let stdin = io::stdin();
 
-        let mut items = s.split(' ');
-        let instruction = items
-            .next()
-            .ok_or(InstructionParseError::EmptyInstruction)?;
-        let operand = items
-            .next()
-            .ok_or_else(|| InstructionParseError::MissingOperand(instruction.to_string()))?;
-        let operand_value = isize::from_str(operand)?;
+    debugger.print_current();
+    loop {
+        print!("(debug) ");
+        io::stdout().flush()?;
 
-        match instruction {
-            "nop" => Ok(Instruction::NOP(operand_value)),
-            "acc" => Ok(Instruction::ACC(operand_value)),
-            "jmp" => Ok(Instruction::JMP(operand_value)),
-            _ => Err(InstructionParseError::UnknownOpcode(
-                instruction.to_string(),
-            )),
+        let mut line = String::new();
+        if stdin.lock().read_line(&mut line)? == 0 {
+            return Ok(());
         }
-    }
-}
 
-impl fmt::Display for Instruction {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match self {
-            Instruction::NOP(s) => write!(f, "NOP {:+}", s),
-            Instruction::ACC(s) => write!(f, "ACC {:+}", s),
-            Instruction::JMP(s) => write!(f, "JMP {:+}", s),
+        match DebugCommand::from_str(&line) {
+            Err(e) => println!("{}", e),
+            Ok(command) => match debugger.execute(command) {
+                Ok(true) => {}
+                Ok(false) => return Ok(()),
+                Err(e) => println!("Execution error: {}", e),
+            },
         }
     }
 }
 
-impl FromStr for Machine {
-    type Err = InstructionParseError;
-
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let mut instructions = Vec::new();
-
-        for line in s.lines() {
-            let instruction = Instruction::from_str(line)?;
-            instructions.push(instruction);
-        }
-
-        Ok(Machine {
-            instructions,
-            accumulator: 0,
-            location: 0,
-        })
-    }
+fn trace(machine: Machine, output: &str, flip: Option<String>) -> Result<(), TopLevelError> {
+    let mut machine = match flip {
+        None => machine,
+        Some(location) => machine
+            .flipped(isize::from_str(&location)?)
+            .ok_or(TopLevelError::NoSolutionFound)?,
+    };
+    let mut file = BufWriter::new(File::create(output)?);
+    let (terminated, last_accum) = machine.trace(&mut file)?;
+    file.flush()?;
+
+    println!(
+        "Wrote trace to {}; machine {} with accumulator {}",
+        output,
+        if terminated { "halted" } else { "looped" },
+        last_accum
+    );
+    Ok(())
 }
 
-impl Machine {
-    fn pretty_print(&self) {
-        for (idx, instr) in self.instructions.iter().enumerate() {
-            let pointer = if (idx as isize) == self.location {
-                "--> "
-            } else {
-                "    "
-            };
-            println!("{} {:04}: {}", pointer, idx, instr);
+fn check(machine: Machine) {
+    for (name, start) in [
+        ("its real starting state", machine.initial_state()),
+        ("any starting state", machine.any_state()),
+    ]
+    .iter()
+    {
+        let result = machine.abstract_run(start.clone());
+        println!("From {}: {:?}", name, result.verdict());
+        if let Some(range) = result.accumulator_range() {
+            println!("  accumulator stays within {}", range);
         }
-    }
-
-    fn step(&mut self) -> Result<(), ExecutionError> {
-        if self.location < 0 || self.location >= (self.instructions.len() as isize) {
-            return Err(ExecutionError::NonExistentLocation(self.location));
+        if let Some(exit) = result.exit {
+            println!("  accumulator on halting: {}", exit.register(ACCUMULATOR));
         }
-
-        match self.instructions[self.location as usize] {
-            Instruction::NOP(_) => self.location += 1,
-            Instruction::JMP(x) => self.location += x,
-            Instruction::ACC(x) => {
-                self.location += 1;
-                self.accumulator += x;
-            }
+        for location in result.faults.iter() {
+            println!("  instruction {:04} might fault", location);
         }
-
-        Ok(())
     }
+}
 
-    fn terminates(&mut self) -> Result<(bool, isize), ExecutionError> {
-        let mut visited_locations = HashSet::new();
-        loop {
-            let current_location = self.location;
-            let current_accumulator = self.accumulator;
-
-            visited_locations.insert(current_location);
-            self.step()?;
-
-            if visited_locations.contains(&self.location) {
-                return Ok((false, current_accumulator));
-            }
+fn main() -> Result<(), TopLevelError> {
+    let filename = env::args().nth(1).expect("No file argument given.");
+    let contents = fs::read_to_string(filename)?;
+    let machine = assemble(&contents)?;
 
-            if self.location == (self.instructions.len() as isize) {
-                return Ok((true, self.accumulator));
-            }
+    match env::args().nth(2).as_deref() {
+        Some("debug") => return debug(machine),
+        Some("check") => {
+            check(machine);
+            return Ok(());
         }
-    }
-
-    fn variants(&self) -> VariantGenerator {
-        VariantGenerator {
-            next_offset: 0,
-            base_machine: self.clone(),
+        Some("disasm") => {
+            print!("{}", disassemble(&machine));
+            return Ok(());
         }
-    }
-}
-
-struct VariantGenerator {
-    next_offset: usize,
-    base_machine: Machine,
-}
-
-impl Iterator for VariantGenerator {
-    type Item = Machine;
-
-    fn next(&mut self) -> Option<Self::Item> {
-        loop {
-            if self.next_offset >= self.base_machine.instructions.len() {
-                return None;
-            }
-
-            match self.base_machine.instructions[self.next_offset] {
-                Instruction::ACC(_) => self.next_offset += 1,
-                Instruction::JMP(x) => {
-                    let mut retval = self.base_machine.clone();
-                    retval.instructions[self.next_offset] = Instruction::NOP(x);
-                    self.next_offset += 1;
-                    return Some(retval);
-                }
-                Instruction::NOP(x) => {
-                    let mut retval = self.base_machine.clone();
-                    retval.instructions[self.next_offset] = Instruction::JMP(x);
-                    self.next_offset += 1;
-                    return Some(retval);
-                }
-            }
+        Some("trace") => {
+            let output = env::args().nth(3).expect("No trace file argument given.");
+            return trace(machine, &output, env::args().nth(4));
+        }
+        Some(other) => {
+            return Err(TopLevelError::BadUsage(format!(
+                "unknown mode {}; try debug, check, disasm or trace",
+                other
+            )))
         }
+        None => {}
     }
-}
-
-fn main() -> Result<(), TopLevelError> {
-    let filename = env::args().nth(1).expect("No file argument given.");
-    let contents = fs::read_to_string(filename)?;
-    let machine = Machine::from_str(&contents)?;
 
     machine.pretty_print();
 
@@ -179,14 +109,36 @@ fn main() -> Result<(), TopLevelError> {
     println!("Last accumulator before looping forever: {}", last_accum);
 
     // this is part 2
-    for mut variant in machine.variants() {
-        if let Ok((true, final_value)) = variant.terminates() {
-            println!(
-                "\nFound a variant that halts! Its last value is {}",
-                final_value
-            );
-            variant.pretty_print();
-        }
+    let flow = machine.control_flow();
+    println!(
+        "{} of {} instructions are unreachable.",
+        flow.unreachable().len(),
+        machine.instructions.len()
+    );
+    for looping in flow.loops() {
+        println!(
+            "Loop of {} instructions, starting at {:04}",
+            looping.len(),
+            looping[0]
+        );
+    }
+
+    let fix = flow
+        .repair(&machine.instructions)
+        .ok_or(TopLevelError::NoSolutionFound)?;
+    let mut variant = machine.flipped(fix).ok_or(TopLevelError::NoSolutionFound)?;
+    if let (true, final_value) = variant.terminates()? {
+        println!(
+            "\nFlipping instruction {:04} makes it halt! Its last value is {}",
+            fix, final_value
+        );
+        variant.pretty_print();
+    } else {
+        println!(
+            "\nFlipping instruction {:04} still doesn't make it halt; no repair found.",
+            fix
+        );
+        return Err(TopLevelError::NoSolutionFound);
     }
 
     Ok(())
@@ -1,162 +1,1414 @@
 use advent2020::errors::{GrammarParseError, TopLevelError};
-use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::collections::{BTreeSet, HashMap, HashSet};
 use std::env;
 use std::fs;
 use std::str::FromStr;
 
 struct Grammar {
-    rules: HashMap<usize, Rule>,
+    rules: HashMap<String, Rule>,
+    /// the rule messages have to match
+    start: String,
 }
 
 impl Grammar {
     fn new() -> Grammar {
         Grammar {
             rules: HashMap::new(),
+            start: "0".to_string(),
         }
     }
 
-    fn add_rule(&mut self, number: usize, rule: Rule) -> Result<(), GrammarParseError> {
-        if self.rules.insert(number, rule).is_some() {
-            Err(GrammarParseError::DuplicateRule(number))
-        } else {
-            Ok(())
+    /// Reads rules from `lines` up to the first blank one, skipping lines
+    /// that are only comments. Messages are matched against rule 0, or the
+    /// first rule in the file if there is no rule 0.
+    fn read<'a, I: Iterator<Item = &'a str>>(lines: &mut I) -> Result<Grammar, GrammarParseError> {
+        let mut grammar = Grammar::new();
+        let mut first = None;
+
+        for (idx, line) in lines.enumerate() {
+            let trimmed = line.trim();
+            if trimmed.is_empty() {
+                break;
+            }
+            if trimmed.starts_with('#') {
+                continue;
+            }
+
+            let at_line = |err| GrammarParseError::AtLine(idx + 1, Box::new(err));
+            let (name, rule) = Rule::new(line).map_err(at_line)?;
+            first.get_or_insert_with(|| name.clone());
+            grammar.add_rule(name, rule).map_err(at_line)?;
+        }
+
+        if !grammar.rules.contains_key(&grammar.start) {
+            if let Some(first) = first {
+                grammar.start = first;
+            }
         }
+
+        Ok(grammar)
     }
 
-    fn parses(&self, s: &str) -> Result<bool, GrammarParseError> {
-        self.accepts(0, s)
-            .map(|x| x.iter().filter(|x| x.is_empty()).count() > 0)
+    fn add_rule(&mut self, name: String, rule: Rule) -> Result<(), GrammarParseError> {
+        match self.rules.entry(name) {
+            Entry::Occupied(entry) => Err(GrammarParseError::DuplicateRule(entry.key().clone())),
+            Entry::Vacant(entry) => {
+                entry.insert(rule);
+                Ok(())
+            }
+        }
     }
 
-    fn accepts<'a>(&self, rule: usize, s: &'a str) -> Result<Vec<&'a str>, GrammarParseError> {
-        match self.rules.get(&rule) {
-            None => Err(GrammarParseError::UnknownRule(rule)),
-            Some(v) => self.rule_accepts(v, s),
+    /// The names of the rules that never lead back round to themselves,
+    /// however far their references are followed. Those describe regular
+    /// languages, so they can be matched by a DFA. Some recursive rules do
+    /// too, like the right-linear `8: 42 | 42 8`, but we don't look for
+    /// them: they go to Earley, and writing `8: 42+` gets the DFA instead.
+    fn regular_rules(&self) -> Result<HashSet<&str>, GrammarParseError> {
+        let mut regular = HashSet::new();
+        let mut recursive = HashSet::new();
+
+        for name in self.rules.keys() {
+            self.classify(name, &mut Vec::new(), &mut regular, &mut recursive)?;
         }
+
+        Ok(regular)
     }
 
-    fn rule_accepts<'a>(&self, rule: &Rule, s: &'a str) -> Result<Vec<&'a str>, GrammarParseError> {
-        match rule {
-            Rule::Alternatives(alts) => {
-                let mut results = Vec::new();
+    fn classify<'a>(
+        &'a self,
+        name: &'a str,
+        path: &mut Vec<&'a str>,
+        regular: &mut HashSet<&'a str>,
+        recursive: &mut HashSet<&'a str>,
+    ) -> Result<bool, GrammarParseError> {
+        if regular.contains(name) {
+            return Ok(true);
+        }
+        if recursive.contains(name) || path.contains(&name) {
+            return Ok(false);
+        }
 
-                for item in alts.iter() {
-                    let mut news = self.rule_accepts(item, s)?;
-                    results.append(&mut news);
-                }
+        let rule = self
+            .rules
+            .get(name)
+            .ok_or_else(|| GrammarParseError::UnknownRule(name.to_string()))?;
+        let mut references = Vec::new();
+        rule.references(&mut references);
 
-                Ok(results)
-            }
+        path.push(name);
+        let mut result = true;
+        for reference in references {
+            // keep going after a recursive one, to report unknown rules
+            result &= self.classify(reference, path, regular, recursive)?;
+        }
+        path.pop();
+
+        if result {
+            regular.insert(name);
+        } else {
+            recursive.insert(name);
+        }
+        Ok(result)
+    }
 
-            Rule::Sequence(seqs) => {
-                let mut results = vec![s];
+    /// A regular expression, in the usual `regex` crate syntax, matching
+    /// exactly the messages the start rule does, if it's regular.
+    fn regex(&self) -> Result<Option<String>, GrammarParseError> {
+        if !self.regular_rules()?.contains(self.start.as_str()) {
+            return Ok(None);
+        }
 
-                for item in seqs.iter() {
-                    let mut new_results = vec![];
+        let mut result = String::from("^");
+        self.write_regex(&self.rules[&self.start], &mut result);
+        result.push('$');
+        Ok(Some(result))
+    }
 
-                    for early_result in results.drain(..) {
-                        let mut nexts = self.rule_accepts(item, early_result)?;
-                        new_results.append(&mut nexts);
+    fn write_regex(&self, rule: &Rule, out: &mut String) {
+        match rule {
+            Rule::Alternatives(alternatives) => {
+                out.push_str("(?:");
+                for (idx, alternative) in alternatives.iter().enumerate() {
+                    if idx > 0 {
+                        out.push('|');
                     }
-
-                    results = new_results;
+                    self.write_regex(alternative, out);
                 }
-
-                Ok(results)
+                out.push(')');
             }
+            Rule::Sequence(members) => {
+                for member in members.iter() {
+                    self.write_regex(member, out);
+                }
+            }
+            Rule::Nonterminal(name) => self.write_regex(&self.rules[name], out),
+            Rule::Terminal(text) => text.chars().for_each(|x| push_escaped(x, out)),
+            Rule::Class(class) => {
+                out.push('[');
+                if class.negated {
+                    out.push('^');
+                }
+                for (lo, hi) in class.ranges.iter() {
+                    push_escaped(*lo, out);
+                    if lo != hi {
+                        out.push('-');
+                        push_escaped(*hi, out);
+                    }
+                }
+                out.push(']');
+            }
+            Rule::Repeat(repeated, repetition) => {
+                let mut inner = repeated.as_ref();
+                while let Rule::Nonterminal(name) = inner {
+                    inner = &self.rules[name];
+                }
+                // anything that isn't a single character or already in
+                // brackets needs a group around it
+                let group = match inner {
+                    Rule::Alternatives(_) | Rule::Class(_) => false,
+                    Rule::Terminal(text) => text.chars().count() != 1,
+                    _ => true,
+                };
 
-            Rule::Nonterminal(new_rule) => self.accepts(*new_rule, s),
-
-            Rule::Terminal(term) => {
-                if let Some(rest) = s.strip_prefix(term) {
-                    Ok(vec![rest])
-                } else {
-                    Ok(vec![])
+                if group {
+                    out.push_str("(?:");
+                }
+                self.write_regex(inner, out);
+                if group {
+                    out.push(')');
                 }
+                out.push(match repetition {
+                    Repetition::Optional => '?',
+                    Repetition::ZeroOrMore => '*',
+                    Repetition::OneOrMore => '+',
+                });
             }
         }
     }
 
+    /// Up to `limit` derivations of `s` from the start rule.
+    fn derivations(&self, s: &str, limit: usize) -> Result<Vec<Tree>, GrammarParseError> {
+        Ok(Earley::new(self, &self.start)?.parse_trees(s, limit))
+    }
+
+    /// Up to `limit` derivations of `s`, and whether there are any more.
+    fn first_derivations(
+        &self,
+        s: &str,
+        limit: usize,
+    ) -> Result<(Vec<Tree>, bool), GrammarParseError> {
+        let mut trees = self.derivations(s, limit.saturating_add(1))?;
+        let more = trees.len() > limit;
+        trees.truncate(limit);
+        Ok((trees, more))
+    }
+
+    /// Only day 19's own rules have a rewrite for part 2.
+    fn can_rewrite(&self) -> bool {
+        ["8", "11", "31", "42"]
+            .iter()
+            .all(|x| self.rules.contains_key(*x))
+    }
+
     fn rewrite(&self) -> Grammar {
+        let rule = |name: &str| Rule::Nonterminal(name.to_string());
         let rule8 = Rule::Alternatives(vec![
-            Rule::Nonterminal(42),
-            Rule::Sequence(vec![Rule::Nonterminal(42), Rule::Nonterminal(8)]),
+            rule("42"),
+            Rule::Sequence(vec![rule("42"), rule("8")]),
         ]);
 
         let rule11 = Rule::Alternatives(vec![
-            Rule::Sequence(vec![Rule::Nonterminal(42), Rule::Nonterminal(31)]),
-            Rule::Sequence(vec![
-                Rule::Nonterminal(42),
-                Rule::Nonterminal(11),
-                Rule::Nonterminal(31),
-            ]),
+            Rule::Sequence(vec![rule("42"), rule("31")]),
+            Rule::Sequence(vec![rule("42"), rule("11"), rule("31")]),
         ]);
 
         let mut rules = self.rules.clone();
-        rules.insert(8, rule8);
-        rules.insert(11, rule11);
-        Grammar { rules }
+        rules.insert("8".to_string(), rule8);
+        rules.insert("11".to_string(), rule11);
+        Grammar {
+            rules,
+            start: self.start.clone(),
+        }
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 enum Rule {
     Alternatives(Vec<Rule>),
     Sequence(Vec<Rule>),
-    Nonterminal(usize),
+    Nonterminal(String),
     Terminal(String),
+    Class(CharClass),
+    Repeat(Box<Rule>, Repetition),
+}
+
+/// What a `?`, `*` or `+` after part of a rule allows.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Repetition {
+    Optional,
+    ZeroOrMore,
+    OneOrMore,
+}
+
+/// A `[...]` character class: any one character in one of the ranges, or
+/// with `^` first, any one character in none of them.
+#[derive(Clone, Debug, PartialEq)]
+struct CharClass {
+    ranges: Vec<(char, char)>,
+    negated: bool,
+}
+
+impl CharClass {
+    fn contains(&self, c: char) -> bool {
+        self.ranges.iter().any(|(lo, hi)| (*lo..=*hi).contains(&c)) != self.negated
+    }
+}
+
+fn push_escaped(c: char, out: &mut String) {
+    if "\\.+*?()|[]{}^$#&-~".contains(c) {
+        out.push('\\');
+    }
+    out.push(c);
 }
 
 impl Rule {
-    fn new(s: &str) -> Result<(usize, Rule), GrammarParseError> {
-        let mut parts = s.split(": ");
-        let rule_num_str = parts
-            .next()
-            .ok_or_else(|| GrammarParseError::BadRule(s.to_string()))?;
-        let rule_num = usize::from_str(rule_num_str)?;
-        let definitions = parts
-            .next()
-            .ok_or_else(|| GrammarParseError::BadRule(s.to_string()))?;
-        let mut alternatives = Vec::new();
-
-        for alternate in definitions.split(" | ") {
-            let trimmed_alternate = alternate.trim();
-            let mut sequence_members = Vec::new();
-
-            for member in trimmed_alternate.split_ascii_whitespace() {
-                let item = if member.starts_with('"') && member.ends_with('"') {
-                    Rule::Terminal(
-                        member
-                            .strip_prefix('"')
-                            .unwrap()
-                            .strip_suffix('"')
-                            .unwrap()
-                            .to_string(),
-                    )
-                } else {
-                    Rule::Nonterminal(usize::from_str(member)?)
+    /// The names of the rules this one refers to.
+    fn references<'a>(&'a self, out: &mut Vec<&'a str>) {
+        match self {
+            Rule::Alternatives(rules) | Rule::Sequence(rules) => {
+                rules.iter().for_each(|x| x.references(out))
+            }
+            Rule::Nonterminal(name) => out.push(name),
+            Rule::Terminal(_) | Rule::Class(_) => {}
+            Rule::Repeat(repeated, _) => repeated.references(out),
+        }
+    }
+
+    /// Parses one rule: a name (numbers are names too), a colon, and
+    /// alternatives separated by `|`. Each alternative is a sequence of
+    /// rule names, `"quoted strings"`, `[a-z]` style character classes and
+    /// `(parenthesised groups)`, any of which can be followed by `?`, `*`
+    /// or `+`. A `#` outside quotes or brackets starts a comment.
+    fn new(s: &str) -> Result<(String, Rule), GrammarParseError> {
+        let mut parser = RuleParser {
+            chars: s.chars().collect(),
+            pos: 0,
+        };
+        let name = parser.name()?;
+        parser.expect(':')?;
+        let rule = parser.alternatives()?;
+
+        match parser.peek() {
+            None | Some('#') => Ok((name, rule)),
+            _ => Err(parser.unexpected()),
+        }
+    }
+}
+
+/// Reads a rule a character at a time. Columns in errors count from 1.
+struct RuleParser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl RuleParser {
+    /// The next character that isn't whitespace, without taking it.
+    fn peek(&mut self) -> Option<char> {
+        while self.chars.get(self.pos).is_some_and(|x| x.is_whitespace()) {
+            self.pos += 1;
+        }
+        self.chars.get(self.pos).cloned()
+    }
+
+    fn unexpected(&self) -> GrammarParseError {
+        match self.chars.get(self.pos) {
+            Some(c) => GrammarParseError::UnexpectedCharacter(*c, self.pos + 1),
+            None => GrammarParseError::UnexpectedEnd(self.pos + 1),
+        }
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), GrammarParseError> {
+        if self.peek() == Some(c) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(self.unexpected())
+        }
+    }
+
+    fn name(&mut self) -> Result<String, GrammarParseError> {
+        self.peek();
+        let start = self.pos;
+        while self
+            .chars
+            .get(self.pos)
+            .is_some_and(|x| x.is_alphanumeric() || *x == '_')
+        {
+            self.pos += 1;
+        }
+
+        if self.pos == start {
+            Err(self.unexpected())
+        } else {
+            Ok(self.chars[start..self.pos].iter().collect())
+        }
+    }
+
+    fn alternatives(&mut self) -> Result<Rule, GrammarParseError> {
+        let mut alternatives = vec![self.sequence()?];
+        while self.peek() == Some('|') {
+            self.pos += 1;
+            alternatives.push(self.sequence()?);
+        }
+
+        if alternatives.len() == 1 {
+            Ok(alternatives.pop().unwrap())
+        } else {
+            Ok(Rule::Alternatives(alternatives))
+        }
+    }
+
+    fn sequence(&mut self) -> Result<Rule, GrammarParseError> {
+        let mut members = Vec::new();
+        while !matches!(self.peek(), None | Some('|') | Some(')') | Some('#')) {
+            members.push(self.repeated()?);
+        }
+
+        match members.len() {
+            0 => Err(self.unexpected()),
+            1 => Ok(members.pop().unwrap()),
+            _ => Ok(Rule::Sequence(members)),
+        }
+    }
+
+    fn repeated(&mut self) -> Result<Rule, GrammarParseError> {
+        let mut rule = self.atom()?;
+        loop {
+            let repetition = match self.chars.get(self.pos) {
+                Some('?') => Repetition::Optional,
+                Some('*') => Repetition::ZeroOrMore,
+                Some('+') => Repetition::OneOrMore,
+                _ => return Ok(rule),
+            };
+            self.pos += 1;
+            rule = Rule::Repeat(Box::new(rule), repetition);
+        }
+    }
+
+    fn atom(&mut self) -> Result<Rule, GrammarParseError> {
+        match self.peek() {
+            Some('"') => self.literal(),
+            Some('[') => self.class(),
+            Some('(') => {
+                self.pos += 1;
+                let rule = self.alternatives()?;
+                self.expect(')')?;
+                Ok(rule)
+            }
+            _ => Ok(Rule::Nonterminal(self.name()?)),
+        }
+    }
+
+    /// The next character inside quotes or brackets, with a backslash
+    /// taking whatever follows it literally.
+    fn quoted_char(&mut self, opened: usize) -> Result<(char, bool), GrammarParseError> {
+        let bracket = self.chars[opened];
+        let unterminated = || GrammarParseError::Unterminated(bracket, opened + 1);
+        let c = *self.chars.get(self.pos).ok_or_else(unterminated)?;
+        self.pos += 1;
+
+        if c == '\\' {
+            let escaped = *self.chars.get(self.pos).ok_or_else(unterminated)?;
+            self.pos += 1;
+            Ok((escaped, true))
+        } else {
+            Ok((c, false))
+        }
+    }
+
+    fn literal(&mut self) -> Result<Rule, GrammarParseError> {
+        let opened = self.pos;
+        self.pos += 1;
+        let mut text = String::new();
+
+        loop {
+            match self.quoted_char(opened)? {
+                ('"', false) => return Ok(Rule::Terminal(text)),
+                (c, _) => text.push(c),
+            }
+        }
+    }
+
+    fn class(&mut self) -> Result<Rule, GrammarParseError> {
+        let opened = self.pos;
+        self.pos += 1;
+        let negated = self.chars.get(self.pos) == Some(&'^');
+        if negated {
+            self.pos += 1;
+        }
+        let mut ranges = Vec::new();
+
+        loop {
+            let lo = match self.quoted_char(opened)? {
+                (']', false) if !ranges.is_empty() => {
+                    return Ok(Rule::Class(CharClass { ranges, negated }))
+                }
+                (c, _) => c,
+            };
+            let hi = if self.chars.get(self.pos) == Some(&'-')
+                && !matches!(self.chars.get(self.pos + 1), None | Some(']'))
+            {
+                self.pos += 1;
+                self.quoted_char(opened)?.0
+            } else {
+                lo
+            };
+
+            if lo > hi {
+                return Err(GrammarParseError::BadRange(lo, hi));
+            }
+            ranges.push((lo, hi));
+        }
+    }
+}
+
+/// One thing to match on the right hand side of a production.
+#[derive(Clone, Debug, PartialEq)]
+enum Symbol {
+    Nonterminal(usize),
+    Text(String),
+    Class(CharClass),
+    /// a whole regular rule at once, by the DFA with this index
+    Regular(usize),
+}
+
+/// A single alternative for one nonterminal, as a flat list of symbols.
+struct Production {
+    lhs: usize,
+    alternative: usize,
+    rhs: Vec<Symbol>,
+}
+
+/// A production with a dot at `dot`, begun at input position `origin`.
+#[derive(Clone, Copy, Eq, Hash, PartialEq)]
+struct Item {
+    production: usize,
+    dot: usize,
+    origin: usize,
+}
+
+#[derive(Default)]
+struct ItemSet {
+    items: Vec<Item>,
+    seen: HashSet<Item>,
+}
+
+impl ItemSet {
+    fn add(&mut self, item: Item) {
+        if self.seen.insert(item) {
+            self.items.push(item);
+        }
+    }
+}
+
+/// An Earley recognizer for a grammar, which copes with any context-free
+/// grammar at all: left recursion, ambiguity, empty rules and all, in at
+/// worst cubic time. The grammar's rules become nonterminals in order of
+/// name, and rules nested inside other rules get nonterminals of their own
+/// after those, named after where they're found: the second member of rule
+/// 8's first alternative is `8[0.1]`, and whatever `8[0.1]` repeats, if it's
+/// a `?`, `*` or `+`, is `8[0.1][repeated]`. Repetitions match left
+/// recursively, so `x+` is `x | x+ x`.
+struct Earley {
+    names: Vec<String>,
+    productions: Vec<Production>,
+    by_lhs: Vec<Vec<usize>>,
+    nullable: Vec<bool>,
+    start: usize,
+    dfas: Vec<Dfa>,
+}
+
+impl Earley {
+    fn new(grammar: &Grammar, start: &str) -> Result<Earley, GrammarParseError> {
+        let mut names: Vec<&String> = grammar.rules.keys().collect();
+        names.sort_unstable();
+        let nonterminals: HashMap<&str, usize> = names
+            .iter()
+            .enumerate()
+            .map(|(idx, name)| (name.as_str(), idx))
+            .collect();
+        let mut result = Earley {
+            names: names.iter().map(|x| x.to_string()).collect(),
+            productions: Vec::new(),
+            by_lhs: vec![Vec::new(); names.len()],
+            nullable: Vec::new(),
+            start: *nonterminals
+                .get(start)
+                .ok_or_else(|| GrammarParseError::UnknownRule(start.to_string()))?,
+            dfas: Vec::new(),
+        };
+
+        for name in names.iter() {
+            result.add_rule(
+                nonterminals[name.as_str()],
+                &grammar.rules[*name],
+                &nonterminals,
+            )?;
+        }
+        result.find_nullable();
+
+        Ok(result)
+    }
+
+    /// Like `new`, but wherever a production refers to a regular rule, that
+    /// rule is matched in one go by a DFA instead. The chart stays much
+    /// smaller that way, though the rule's insides aren't in it any more.
+    fn compiled(grammar: &Grammar, start: &str) -> Result<Earley, GrammarParseError> {
+        let regular = grammar.regular_rules()?;
+        let mut result = Earley::new(grammar, start)?;
+        let mut compiled = HashMap::new();
+        let Earley {
+            names,
+            productions,
+            dfas,
+            ..
+        } = &mut result;
+
+        for symbol in productions.iter_mut().flat_map(|x| x.rhs.iter_mut()) {
+            if let Symbol::Nonterminal(nonterminal) = symbol {
+                let name = names[*nonterminal].as_str();
+                if regular.contains(name) {
+                    let idx = *compiled.entry(*nonterminal).or_insert_with(|| {
+                        dfas.push(Dfa::new(grammar, &grammar.rules[name]));
+                        dfas.len() - 1
+                    });
+                    *symbol = Symbol::Regular(idx);
+                }
+            }
+        }
+        result.find_nullable();
+
+        Ok(result)
+    }
+
+    fn add_rule(
+        &mut self,
+        lhs: usize,
+        rule: &Rule,
+        nonterminals: &HashMap<&str, usize>,
+    ) -> Result<(), GrammarParseError> {
+        if let Rule::Repeat(repeated, repetition) = rule {
+            let name = format!("{}[repeated]", self.names[lhs]);
+            let item = self.symbol(repeated, name, nonterminals)?;
+            let lhs_symbol = Symbol::Nonterminal(lhs);
+            let alternatives = match repetition {
+                Repetition::Optional => vec![vec![], vec![item]],
+                Repetition::ZeroOrMore => vec![vec![], vec![lhs_symbol, item]],
+                Repetition::OneOrMore => vec![vec![item.clone()], vec![lhs_symbol, item]],
+            };
+            for (number, rhs) in alternatives.into_iter().enumerate() {
+                self.add_production(lhs, number, rhs);
+            }
+            return Ok(());
+        }
+
+        let alternatives = match rule {
+            Rule::Alternatives(alts) => alts.iter().collect(),
+            _ => vec![rule],
+        };
+
+        for (number, alternative) in alternatives.into_iter().enumerate() {
+            let members = match alternative {
+                Rule::Sequence(members) => members.iter().collect(),
+                _ => vec![alternative],
+            };
+            let mut rhs = Vec::with_capacity(members.len());
+            for (position, member) in members.into_iter().enumerate() {
+                let name = format!("{}[{}.{}]", self.names[lhs], number, position);
+                rhs.push(self.symbol(member, name, nonterminals)?);
+            }
+            self.add_production(lhs, number, rhs);
+        }
+
+        Ok(())
+    }
+
+    fn add_production(&mut self, lhs: usize, alternative: usize, rhs: Vec<Symbol>) {
+        self.by_lhs[lhs].push(self.productions.len());
+        self.productions.push(Production {
+            lhs,
+            alternative,
+            rhs,
+        });
+    }
+
+    fn symbol(
+        &mut self,
+        rule: &Rule,
+        name: String,
+        nonterminals: &HashMap<&str, usize>,
+    ) -> Result<Symbol, GrammarParseError> {
+        match rule {
+            Rule::Nonterminal(name) => nonterminals
+                .get(name.as_str())
+                .map(|x| Symbol::Nonterminal(*x))
+                .ok_or_else(|| GrammarParseError::UnknownRule(name.clone())),
+            Rule::Terminal(text) => Ok(Symbol::Text(text.clone())),
+            Rule::Class(class) => Ok(Symbol::Class(class.clone())),
+            _ => {
+                let lhs = self.by_lhs.len();
+                self.by_lhs.push(Vec::new());
+                self.names.push(name);
+                self.add_rule(lhs, rule, nonterminals)?;
+                Ok(Symbol::Nonterminal(lhs))
+            }
+        }
+    }
+
+    fn find_nullable(&mut self) {
+        self.nullable = vec![false; self.by_lhs.len()];
+
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for production in self.productions.iter() {
+                if !self.nullable[production.lhs]
+                    && production.rhs.iter().all(|x| match x {
+                        Symbol::Nonterminal(nt) => self.nullable[*nt],
+                        Symbol::Text(text) => text.is_empty(),
+                        Symbol::Class(_) => false,
+                        Symbol::Regular(idx) => self.dfas[*idx].matches(""),
+                    })
+                {
+                    self.nullable[production.lhs] = true;
+                    changed = true;
+                }
+            }
+        }
+    }
+
+    /// The item sets for every position in `s`.
+    fn chart(&self, s: &str) -> Vec<ItemSet> {
+        let input = s.as_bytes();
+        let mut chart: Vec<ItemSet> = (0..=input.len()).map(|_| ItemSet::default()).collect();
+
+        for production in self.by_lhs[self.start].iter() {
+            chart[0].add(Item {
+                production: *production,
+                dot: 0,
+                origin: 0,
+            });
+        }
+
+        for pos in 0..=input.len() {
+            let mut idx = 0;
+
+            while idx < chart[pos].items.len() {
+                let item = chart[pos].items[idx];
+                let production = &self.productions[item.production];
+                let advanced = Item {
+                    dot: item.dot + 1,
+                    ..item
                 };
-                sequence_members.push(item);
+                idx += 1;
+
+                match production.rhs.get(item.dot) {
+                    // complete: move along everything that was waiting on
+                    // this nonterminal. Empty matches were already dealt with
+                    // when they were predicted, so this never needs to see
+                    // items added to the set it's reading from.
+                    None => {
+                        let waiting: Vec<Item> = chart[item.origin]
+                            .items
+                            .iter()
+                            .filter(|x| {
+                                self.productions[x.production].rhs.get(x.dot)
+                                    == Some(&Symbol::Nonterminal(production.lhs))
+                            })
+                            .map(|x| Item {
+                                dot: x.dot + 1,
+                                ..*x
+                            })
+                            .collect();
+                        for parent in waiting {
+                            chart[pos].add(parent);
+                        }
+                    }
+                    // predict
+                    Some(Symbol::Nonterminal(nt)) => {
+                        for next in self.by_lhs[*nt].iter() {
+                            chart[pos].add(Item {
+                                production: *next,
+                                dot: 0,
+                                origin: pos,
+                            });
+                        }
+                        if self.nullable[*nt] {
+                            chart[pos].add(advanced);
+                        }
+                    }
+                    // scan
+                    Some(Symbol::Text(text)) => {
+                        if input[pos..].starts_with(text.as_bytes()) {
+                            chart[pos + text.len()].add(advanced);
+                        }
+                    }
+                    Some(Symbol::Class(class)) => {
+                        if let Some(c) = s[pos..].chars().next().filter(|x| class.contains(*x)) {
+                            chart[pos + c.len_utf8()].add(advanced);
+                        }
+                    }
+                    Some(Symbol::Regular(idx)) => {
+                        for end in self.dfas[*idx].match_ends(s, pos) {
+                            chart[end].add(advanced);
+                        }
+                    }
+                }
+            }
+        }
+
+        chart
+    }
+
+    /// Up to `limit` of the ways the start rule can match all of `s`.
+    /// Derivations that loop back to the same rule over the same stretch of
+    /// the message are left out, since there would be no end of them.
+    fn parse_trees(&self, s: &str, limit: usize) -> Vec<Tree> {
+        let chart = self.chart(s);
+        let mut forest = Forest {
+            earley: self,
+            input: s,
+            complete: HashSet::new(),
+            spans: HashSet::new(),
+            active: HashSet::new(),
+            limit,
+        };
+
+        for (end, set) in chart.iter().enumerate() {
+            for item in set.items.iter() {
+                let production = &self.productions[item.production];
+                if item.dot == production.rhs.len() {
+                    forest.complete.insert((item.production, item.origin, end));
+                    forest.spans.insert((production.lhs, item.origin, end));
+                }
+            }
+        }
+
+        forest.trees(self.start, 0, s.len())
+    }
+
+    fn recognizes(&self, s: &str) -> bool {
+        let chart = self.chart(s);
+
+        chart[s.len()].items.iter().any(|x| {
+            x.origin == 0
+                && self.productions[x.production].lhs == self.start
+                && x.dot == self.productions[x.production].rhs.len()
+        })
+    }
+}
+
+/// How one rule, or one piece of text, matched part of a message.
+#[derive(Clone, Debug, PartialEq)]
+enum Tree {
+    Rule {
+        name: String,
+        /// which of the rule's alternatives, counting from 0
+        alternative: usize,
+        start: usize,
+        end: usize,
+        children: Vec<Tree>,
+    },
+    Text {
+        text: String,
+        start: usize,
+    },
+}
+
+impl Tree {
+    fn indented(&self, message: &str) -> String {
+        let mut result = String::new();
+        self.write_indented(message, 0, &mut result);
+        result
+    }
+
+    fn write_indented(&self, message: &str, depth: usize, out: &mut String) {
+        for _ in 0..depth {
+            out.push_str("  ");
+        }
+
+        match self {
+            Tree::Rule {
+                name,
+                alternative,
+                start,
+                end,
+                children,
+            } => {
+                out.push_str(&format!(
+                    "{} (alternative {}): {:?}\n",
+                    name,
+                    alternative + 1,
+                    &message[*start..*end]
+                ));
+                for child in children.iter() {
+                    child.write_indented(message, depth + 1, out);
+                }
+            }
+            Tree::Text { text, .. } => out.push_str(&format!("{:?}\n", text)),
+        }
+    }
+
+    fn write_dot(&self, message: &str, prefix: &str, next: &mut usize, out: &mut String) -> String {
+        let id = format!("{}n{}", prefix, next);
+        *next += 1;
+
+        let label = match self {
+            Tree::Rule {
+                name,
+                alternative,
+                start,
+                end,
+                ..
+            } => format!(
+                "{} #{}\\n{:?}",
+                name,
+                alternative + 1,
+                &message[*start..*end]
+            ),
+            Tree::Text { text, .. } => format!("{:?}", text),
+        };
+        out.push_str(&format!(
+            "    {} [label=\"{}\"{}];\n",
+            id,
+            label.replace('"', "\\\""),
+            if let Tree::Text { .. } = self {
+                ", shape=box"
+            } else {
+                ""
+            }
+        ));
+
+        if let Tree::Rule { children, .. } = self {
+            for child in children.iter() {
+                let child_id = child.write_dot(message, prefix, next, out);
+                out.push_str(&format!("    {} -> {};\n", id, child_id));
+            }
+        }
+
+        id
+    }
+}
+
+/// Every derivation as one Graphviz graph, each in its own cluster.
+fn dot(trees: &[Tree], message: &str, more: bool) -> String {
+    let mut result = String::from("digraph derivations {\n");
+    result.push_str(&format!("  label=\"{}\";\n", found(trees.len(), more)));
+
+    for (idx, tree) in trees.iter().enumerate() {
+        result.push_str(&format!(
+            "  subgraph cluster_{} {{\n    label=\"derivation {}\";\n",
+            idx,
+            idx + 1
+        ));
+        tree.write_dot(message, &format!("d{}", idx), &mut 0, &mut result);
+        result.push_str("  }\n");
+    }
+
+    result.push_str("}\n");
+    result
+}
+
+/// How many derivations there are, as far as we looked.
+fn found(count: usize, more: bool) -> String {
+    if more {
+        format!(
+            "Showing the first {} of more than {} derivations",
+            count, count
+        )
+    } else {
+        format!("{} derivation(s) found", count)
+    }
+}
+
+/// What a finished Earley chart says about a message, for reading trees
+/// back out of it.
+struct Forest<'a> {
+    earley: &'a Earley,
+    input: &'a str,
+    /// every (production, start, end) that matched
+    complete: HashSet<(usize, usize, usize)>,
+    /// every (nonterminal, start, end) that matched
+    spans: HashSet<(usize, usize, usize)>,
+    /// the (nonterminal, start, end)s being worked out right now
+    active: HashSet<(usize, usize, usize)>,
+    limit: usize,
+}
+
+impl<'a> Forest<'a> {
+    fn trees(&mut self, nonterminal: usize, start: usize, end: usize) -> Vec<Tree> {
+        let earley = self.earley;
+        let mut result = Vec::new();
+
+        if !self.active.insert((nonterminal, start, end)) {
+            return result;
+        }
+
+        for idx in earley.by_lhs[nonterminal].iter() {
+            if !self.complete.contains(&(*idx, start, end)) {
+                continue;
             }
 
-            match sequence_members.len() {
-                0 => return Err(GrammarParseError::BadRule(s.to_string())),
-                1 => alternatives.push(sequence_members.pop().unwrap()),
-                _ => alternatives.push(Rule::Sequence(sequence_members)),
+            let production = &earley.productions[*idx];
+            for children in self.sequences(&production.rhs, start, end) {
+                if result.len() == self.limit {
+                    break;
+                }
+                result.push(Tree::Rule {
+                    name: earley.names[nonterminal].clone(),
+                    alternative: production.alternative,
+                    start,
+                    end,
+                    children,
+                });
             }
         }
 
-        match alternatives.len() {
-            0 => Err(GrammarParseError::BadRule(s.to_string())),
-            1 => Ok((rule_num, alternatives.pop().unwrap())),
-            _ => Ok((rule_num, Rule::Alternatives(alternatives))),
+        self.active.remove(&(nonterminal, start, end));
+        result
+    }
+
+    /// The ways `rhs` can match exactly `start..end`.
+    fn sequences(&mut self, rhs: &[Symbol], start: usize, end: usize) -> Vec<Vec<Tree>> {
+        let mut result = Vec::new();
+
+        match rhs.split_first() {
+            None if start == end => result.push(Vec::new()),
+            None => {}
+            Some((Symbol::Text(text), rest))
+                if self.input.as_bytes()[start..end].starts_with(text.as_bytes()) =>
+            {
+                for mut tail in self.sequences(rest, start + text.len(), end) {
+                    tail.insert(
+                        0,
+                        Tree::Text {
+                            text: text.clone(),
+                            start,
+                        },
+                    );
+                    result.push(tail);
+                }
+            }
+            Some((Symbol::Text(_), _)) => {}
+            Some((Symbol::Class(class), rest)) => {
+                let found = self.input[start..end].chars().next();
+                if let Some(c) = found.filter(|x| class.contains(*x)) {
+                    for mut tail in self.sequences(rest, start + c.len_utf8(), end) {
+                        tail.insert(
+                            0,
+                            Tree::Text {
+                                text: c.to_string(),
+                                start,
+                            },
+                        );
+                        result.push(tail);
+                    }
+                }
+            }
+            Some((Symbol::Regular(idx), rest)) => {
+                let earley = self.earley;
+                for split in earley.dfas[*idx].match_ends(&self.input[..end], start) {
+                    for mut tail in self.sequences(rest, split, end) {
+                        tail.insert(
+                            0,
+                            Tree::Text {
+                                text: self.input[start..split].to_string(),
+                                start,
+                            },
+                        );
+                        result.push(tail);
+                    }
+                }
+            }
+            Some((Symbol::Nonterminal(nonterminal), rest)) => {
+                for split in start..=end {
+                    if result.len() >= self.limit {
+                        break;
+                    }
+                    if !self.spans.contains(&(*nonterminal, start, split)) {
+                        continue;
+                    }
+                    let tails = self.sequences(rest, split, end);
+                    if tails.is_empty() {
+                        continue;
+                    }
+                    for head in self.trees(*nonterminal, start, split) {
+                        for tail in tails.iter() {
+                            let mut children = vec![head.clone()];
+                            children.extend(tail.iter().cloned());
+                            result.push(children);
+                        }
+                    }
+                }
+            }
         }
+
+        result.truncate(self.limit);
+        result
     }
 }
 
+/// A minimized DFA for a regular rule, which checks a message in one pass.
+/// Characters are grouped into runs that every terminal and character
+/// class in the rule treats alike, so there's one column of transitions
+/// per run rather than one per character.
+#[derive(Debug)]
+struct Dfa {
+    /// the first character of each run, in order, starting with '\0'
+    starts: Vec<char>,
+    /// the state after `state` sees a character from run `run` is
+    /// `transitions[state * starts.len() + run]`; state 0 is the start
+    transitions: Vec<usize>,
+    accepting: Vec<bool>,
+    /// states that can never get to an accepting one
+    dead: Vec<bool>,
+}
+
+impl Dfa {
+    /// Compiles `rule`, which must only refer to regular rules of `grammar`.
+    fn new(grammar: &Grammar, rule: &Rule) -> Dfa {
+        let mut cuts = BTreeSet::new();
+        cuts.insert('\0');
+        find_cuts(grammar, rule, &mut cuts);
+        let mut nfa = Nfa {
+            grammar,
+            starts: cuts.into_iter().collect(),
+            epsilon: Vec::new(),
+            moves: Vec::new(),
+        };
+        let start = nfa.state();
+        let accept = nfa.add(rule, start);
+
+        let (transitions, accepting) = nfa.determinize(start, accept);
+        let mut result = minimize(nfa.starts, transitions, accepting);
+        result.find_dead();
+        result
+    }
+
+    fn step(&self, state: usize, c: char) -> usize {
+        let run = self.starts.partition_point(|x| *x <= c) - 1;
+        self.transitions[state * self.starts.len() + run]
+    }
+
+    fn matches(&self, s: &str) -> bool {
+        let mut state = 0;
+
+        for c in s.chars() {
+            state = self.step(state, c);
+            if self.dead[state] {
+                return false;
+            }
+        }
+
+        self.accepting[state]
+    }
+
+    /// Every position a match starting at byte `from` of `s` can end at.
+    fn match_ends(&self, s: &str, from: usize) -> Vec<usize> {
+        let mut result = Vec::new();
+        let mut state = 0;
+        if self.accepting[state] {
+            result.push(from);
+        }
+
+        for (idx, c) in s[from..].char_indices() {
+            state = self.step(state, c);
+            if self.dead[state] {
+                break;
+            }
+            if self.accepting[state] {
+                result.push(from + idx + c.len_utf8());
+            }
+        }
+
+        result
+    }
+
+    fn find_dead(&mut self) {
+        let width = self.starts.len();
+        let mut live = self.accepting.clone();
+
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for state in 0..live.len() {
+                if !live[state]
+                    && self.transitions[state * width..(state + 1) * width]
+                        .iter()
+                        .any(|x| live[*x])
+                {
+                    live[state] = true;
+                    changed = true;
+                }
+            }
+        }
+
+        self.dead = live.into_iter().map(|x| !x).collect();
+    }
+}
+
+/// The character after `c`, skipping the gap where the surrogates would be.
+fn after(c: char) -> Option<char> {
+    match c {
+        '\u{D7FF}' => Some('\u{E000}'),
+        _ => std::char::from_u32(c as u32 + 1),
+    }
+}
+
+/// Adds every character where what `rule` accepts might change.
+fn find_cuts(grammar: &Grammar, rule: &Rule, cuts: &mut BTreeSet<char>) {
+    let mut add = |lo: char, hi: char| {
+        cuts.insert(lo);
+        cuts.extend(after(hi));
+    };
+
+    match rule {
+        Rule::Alternatives(rules) | Rule::Sequence(rules) => {
+            rules.iter().for_each(|x| find_cuts(grammar, x, cuts))
+        }
+        Rule::Nonterminal(name) => find_cuts(grammar, &grammar.rules[name], cuts),
+        Rule::Terminal(text) => text.chars().for_each(|x| add(x, x)),
+        Rule::Class(class) => class.ranges.iter().for_each(|(lo, hi)| add(*lo, *hi)),
+        Rule::Repeat(repeated, _) => find_cuts(grammar, repeated, cuts),
+    }
+}
+
+/// A nondeterministic automaton with empty moves, built the Thompson way,
+/// on the way to a DFA.
+struct Nfa<'a> {
+    grammar: &'a Grammar,
+    starts: Vec<char>,
+    epsilon: Vec<Vec<usize>>,
+    /// (run of characters, next state)
+    moves: Vec<Vec<(usize, usize)>>,
+}
+
+impl<'a> Nfa<'a> {
+    fn state(&mut self) -> usize {
+        self.epsilon.push(Vec::new());
+        self.moves.push(Vec::new());
+        self.epsilon.len() - 1
+    }
+
+    /// Adds states matching `rule` from `from`, returning the state they
+    /// finish in.
+    fn add(&mut self, rule: &Rule, from: usize) -> usize {
+        match rule {
+            Rule::Alternatives(alternatives) => {
+                let to = self.state();
+                for alternative in alternatives.iter() {
+                    let start = self.state();
+                    self.epsilon[from].push(start);
+                    let end = self.add(alternative, start);
+                    self.epsilon[end].push(to);
+                }
+                to
+            }
+            Rule::Sequence(members) => members.iter().fold(from, |x, y| self.add(y, x)),
+            Rule::Nonterminal(name) => {
+                let grammar = self.grammar;
+                self.add(&grammar.rules[name], from)
+            }
+            Rule::Terminal(text) => text.chars().fold(from, |x, c| {
+                let to = self.state();
+                let run = self.starts.partition_point(|y| *y <= c) - 1;
+                self.moves[x].push((run, to));
+                to
+            }),
+            Rule::Class(class) => {
+                let to = self.state();
+                for (run, c) in self.starts.iter().enumerate() {
+                    if class.contains(*c) {
+                        self.moves[from].push((run, to));
+                    }
+                }
+                to
+            }
+            Rule::Repeat(repeated, repetition) => {
+                // `looped` is between repetitions, so it mustn't share
+                // anything with whatever comes before or after
+                let looped = self.state();
+                self.epsilon[from].push(looped);
+                let end = self.add(repeated, looped);
+
+                match repetition {
+                    Repetition::ZeroOrMore => {
+                        self.epsilon[end].push(looped);
+                        let to = self.state();
+                        self.epsilon[looped].push(to);
+                        to
+                    }
+                    Repetition::OneOrMore => {
+                        let to = self.state();
+                        self.epsilon[end].push(to);
+                        self.epsilon[to].push(looped);
+                        let after = self.state();
+                        self.epsilon[to].push(after);
+                        after
+                    }
+                    Repetition::Optional => {
+                        let to = self.state();
+                        self.epsilon[looped].push(to);
+                        self.epsilon[end].push(to);
+                        to
+                    }
+                }
+            }
+        }
+    }
+
+    fn closure(&self, mut states: Vec<usize>) -> Vec<usize> {
+        let mut idx = 0;
+
+        while idx < states.len() {
+            for next in self.epsilon[states[idx]].iter() {
+                if !states.contains(next) {
+                    states.push(*next);
+                }
+            }
+            idx += 1;
+        }
+
+        states.sort_unstable();
+        states
+    }
+
+    /// The subset construction: one DFA state per set of NFA states we can
+    /// be in at once, with the empty set as a state that never gets out.
+    fn determinize(&self, start: usize, accept: usize) -> (Vec<usize>, Vec<bool>) {
+        let width = self.starts.len();
+        let mut sets = vec![self.closure(vec![start])];
+        let mut known: HashMap<Vec<usize>, usize> = HashMap::new();
+        known.insert(sets[0].clone(), 0);
+        let mut transitions = Vec::new();
+        let mut idx = 0;
+
+        while idx < sets.len() {
+            for run in 0..width {
+                let mut targets = Vec::new();
+                for state in sets[idx].iter() {
+                    for (_, next) in self.moves[*state].iter().filter(|x| x.0 == run) {
+                        if !targets.contains(next) {
+                            targets.push(*next);
+                        }
+                    }
+                }
+
+                let targets = self.closure(targets);
+                let next = match known.get(&targets) {
+                    Some(next) => *next,
+                    None => {
+                        known.insert(targets.clone(), sets.len());
+                        sets.push(targets);
+                        sets.len() - 1
+                    }
+                };
+                transitions.push(next);
+            }
+            idx += 1;
+        }
+
+        let accepting = sets.iter().map(|x| x.contains(&accept)).collect();
+        (transitions, accepting)
+    }
+}
+
+/// Moore's algorithm: split the states by whether they accept, then keep
+/// splitting groups whose members go to different groups on the same run
+/// of characters, until nothing changes. Each group left is one state.
+fn minimize(starts: Vec<char>, transitions: Vec<usize>, accepting: Vec<bool>) -> Dfa {
+    let width = starts.len();
+    let mut group: Vec<usize> = accepting.iter().map(|x| *x as usize).collect();
+    let mut groups = group.iter().collect::<HashSet<_>>().len();
+
+    loop {
+        let mut signatures = HashMap::new();
+        let mut next = Vec::with_capacity(group.len());
+
+        for state in 0..group.len() {
+            let signature: Vec<usize> = std::iter::once(group[state])
+                .chain(
+                    transitions[state * width..(state + 1) * width]
+                        .iter()
+                        .map(|x| group[*x]),
+                )
+                .collect();
+            let count = signatures.len();
+            next.push(*signatures.entry(signature).or_insert(count));
+        }
+
+        // groups are numbered in order of first member, so the start
+        // state's is always 0
+        group = next;
+        if signatures.len() == groups {
+            break;
+        }
+        groups = signatures.len();
+    }
+
+    let mut result = Dfa {
+        starts,
+        transitions: vec![0; groups * width],
+        accepting: vec![false; groups],
+        dead: Vec::new(),
+    };
+    for (state, mine) in group.iter().enumerate() {
+        result.accepting[*mine] = accepting[state];
+        for run in 0..width {
+            result.transitions[mine * width + run] = group[transitions[state * width + run]];
+        }
+    }
+
+    result
+}
+
+/// The quickest way to check messages against a grammar.
+enum Matcher {
+    /// the start rule is regular, so a single DFA will do
+    Regular(Dfa),
+    /// it's not, but whatever regular rules it uses are DFAs inside Earley
+    General(Earley),
+}
+
+impl Matcher {
+    fn new(grammar: &Grammar) -> Result<Matcher, GrammarParseError> {
+        if grammar.regular_rules()?.contains(grammar.start.as_str()) {
+            Ok(Matcher::Regular(Dfa::new(
+                grammar,
+                &grammar.rules[&grammar.start],
+            )))
+        } else {
+            Ok(Matcher::General(Earley::compiled(grammar, &grammar.start)?))
+        }
+    }
+
+    fn matches(&self, s: &str) -> bool {
+        match self {
+            Matcher::Regular(dfa) => dfa.matches(s),
+            Matcher::General(earley) => earley.recognizes(s),
+        }
+    }
+}
+
+// How many derivations to show for an ambiguous message, unless asked for
+// more.
+const DERIVATION_LIMIT: usize = 20;
+
 macro_rules! run_parser {
     ($parser: ident, $line: ident, $count: ident) => {
-        if $parser.parses($line)? {
+        if $parser.matches($line) {
             $count += 1;
             "YES"
         } else {
@@ -169,42 +1421,377 @@ macro_rules! run_parser {
 fn rewrite_test() {
     let line = "aaaaabbaabaaaaababaa";
     let contents = fs::read_to_string("inputs/day19_test2.txt").unwrap();
-    let mut grammar = Grammar::new();
-    for line in contents.lines() {
-        if line == "" {
-            break;
-        }
+    let grammar = Grammar::read(&mut contents.lines()).unwrap();
+    let rewritten = grammar.rewrite();
+    assert!(!Matcher::new(&grammar).unwrap().matches(line));
+    assert!(Matcher::new(&rewritten).unwrap().matches(line));
+}
 
-        let (num, rule) = Rule::new(line).unwrap();
-        grammar.add_rule(num, rule).unwrap();
-    }
+#[cfg(test)]
+fn grammar_of(rules: &str) -> Grammar {
+    Grammar::read(&mut rules.lines()).unwrap()
+}
+
+#[cfg(test)]
+fn matcher_of(rules: &str) -> Matcher {
+    Matcher::new(&grammar_of(rules)).unwrap()
+}
+
+/// The messages after the rules, in a file `grammar_of` can read.
+#[cfg(test)]
+fn messages_of(contents: &str) -> Vec<&str> {
+    contents
+        .lines()
+        .skip_while(|x| !x.is_empty())
+        .skip(1)
+        .collect()
+}
+
+#[test]
+fn earley_test() {
+    let contents = fs::read_to_string("inputs/day19_test2.txt").unwrap();
+    let messages = messages_of(&contents);
+    let grammar = grammar_of(&contents);
+    let count = |grammar: &Grammar| {
+        let matcher = Matcher::new(grammar).unwrap();
+        messages.iter().filter(|x| matcher.matches(x)).count()
+    };
+    assert_eq!(3, count(&grammar));
+    assert_eq!(12, count(&grammar.rewrite()));
+
+    // the same loops, written left recursively
+    let mut left = grammar_of(&contents);
+    left.rules
+        .insert("8".to_string(), Rule::new("8: 42 | 8 42").unwrap().1);
+    left.rules.insert(
+        "11".to_string(),
+        Rule::new("11: 42 31 | 42 11 31").unwrap().1,
+    );
+    assert_eq!(12, count(&left));
+
+    // ambiguous, and with empty matches
+    let ambiguous = matcher_of("0: 0 0 | 1\n1: \"a\" | \"\"\n");
+    assert!(ambiguous.matches("aaa"));
+    assert!(ambiguous.matches(""));
+    assert!(!ambiguous.matches("aba"));
+    let nested = matcher_of("0: 1 2 1\n1: 2 | \"x\"\n2: \"\"\n");
+    assert!(nested.matches("xx"));
+    assert!(nested.matches("x"));
+    assert!(!nested.matches("xxx"));
+
+    // right-linear, so regular in fact, but recursion always goes to Earley
+    let longer = matcher_of("0: \"ab\" 0 | \"ab\"\n");
+    assert!(matches!(longer, Matcher::General(_)));
+    assert!(longer.matches("ababab"));
+    assert!(!longer.matches("ababa"));
+    assert!(matches!(matcher_of("0: \"ab\"+\n"), Matcher::Regular(_)));
+
+    let broken = grammar_of("0: 1 2\n1: \"a\"\n");
+    assert_eq!(
+        Some(GrammarParseError::UnknownRule("2".to_string())),
+        Matcher::new(&broken).err()
+    );
+    assert_eq!(
+        Some(GrammarParseError::UnknownRule("5".to_string())),
+        Earley::new(&broken, "5").err()
+    );
+}
+
+#[test]
+fn syntax_test() {
+    assert_eq!(
+        Ok((
+            "word".to_string(),
+            Rule::Sequence(vec![
+                Rule::Repeat(
+                    Box::new(Rule::Terminal("a \"b\"".to_string())),
+                    Repetition::OneOrMore
+                ),
+                Rule::Repeat(
+                    Box::new(Rule::Class(CharClass {
+                        ranges: vec![('a', 'c'), ('-', '-'), (']', ']')],
+                        negated: true,
+                    })),
+                    Repetition::Optional
+                ),
+            ])
+        )),
+        Rule::new(r#"word: "a \"b\""+ [^a-c-\]]? # trailing comment"#)
+    );
+
+    let grammar = grammar_of(
+        "# a greeting, or a list of them\n\
+         message: greeting (\", \" greeting)*\n\
+         # who to greet\n\
+         greeting: (\"hello\" | \"hi\") \" \"+ name # any number of spaces\n\
+         name: [A-Z] [a-z]* \"!\"?\n",
+    );
+    assert_eq!("message", grammar.start);
+    let greetings = Matcher::new(&grammar).unwrap();
+    assert!(greetings.matches("hello Bob"));
+    assert!(greetings.matches("hi  Al!, hello X"));
+    assert!(!greetings.matches("hello bob"));
+    assert!(!greetings.matches("hi Al,"));
+    assert!(!greetings.matches("hiAl"));
+    let no_e = matcher_of("0: [^é]+\n");
+    assert!(no_e.matches("naïve"));
+    assert!(!no_e.matches("café"));
+
+    // part 2's loops, written with repetition
+    let contents = fs::read_to_string("inputs/day19_test2.txt").unwrap();
+    let mut grammar = grammar_of(&contents);
+    grammar
+        .rules
+        .insert("8".to_string(), Rule::new("8: 42+").unwrap().1);
+    grammar
+        .rules
+        .insert("11".to_string(), Rule::new("11: 42 11? 31").unwrap().1);
+    let matcher = Matcher::new(&grammar).unwrap();
+    assert_eq!(
+        12,
+        messages_of(&contents)
+            .iter()
+            .filter(|x| matcher.matches(x))
+            .count()
+    );
+
+    let error = |rules: &str| Grammar::read(&mut rules.lines()).err();
+    let at_line = |line, err| Some(GrammarParseError::AtLine(line, Box::new(err)));
+    assert_eq!(
+        at_line(1, GrammarParseError::UnexpectedEnd(7)),
+        error("0: 1 |")
+    );
+    assert_eq!(
+        at_line(2, GrammarParseError::UnexpectedCharacter(')', 7)),
+        error("# start\n0: 1 2)\n")
+    );
+    assert_eq!(
+        at_line(1, GrammarParseError::UnexpectedCharacter('"', 1)),
+        error("\"a\": 1")
+    );
+    assert_eq!(
+        at_line(1, GrammarParseError::Unterminated('"', 4)),
+        error("0: \"ab|c")
+    );
+    assert_eq!(
+        at_line(1, GrammarParseError::Unterminated('[', 6)),
+        error("0: 1 [a-z")
+    );
+    assert_eq!(
+        at_line(1, GrammarParseError::UnexpectedEnd(8)),
+        error("0: (1 2")
+    );
+    assert_eq!(
+        at_line(1, GrammarParseError::BadRange('z', 'a')),
+        error("0: [z-a]")
+    );
+    assert_eq!(
+        at_line(3, GrammarParseError::DuplicateRule("a".to_string())),
+        error("a: b\nb: \"x\"\na: b b\n")
+    );
+    assert_eq!(
+        "Line 3: Duplicate rule definition for a",
+        error("a: b\nb: \"x\"\na: b b\n").unwrap().to_string()
+    );
+}
+
+#[test]
+fn dfa_test() {
+    let contents = fs::read_to_string("inputs/day19_test1.txt").unwrap();
+    let grammar = grammar_of(&contents);
+    assert!(matches!(Matcher::new(&grammar), Ok(Matcher::Regular(_))));
+    assert_eq!(
+        Ok(Some(
+            "^a(?:(?:aa|bb)(?:ab|ba)|(?:ab|ba)(?:aa|bb))b$".to_string()
+        )),
+        grammar.regex()
+    );
+
+    // the DFAs and Earley on its own should always agree
+    let contents = fs::read_to_string("inputs/day19_test2.txt").unwrap();
+    let messages = messages_of(&contents);
+    let grammar = grammar_of(&contents);
     let rewritten = grammar.rewrite();
-    assert_eq!(Ok(false), grammar.parses(line));
-    assert_eq!(Ok(true), rewritten.parses(line));
+    let dfa = Dfa::new(&grammar, &grammar.rules["0"]);
+    let general = Earley::new(&grammar, "0").unwrap();
+    let compiled = Earley::compiled(&rewritten, "0").unwrap();
+    let earley = Earley::new(&rewritten, "0").unwrap();
+    for message in messages.iter() {
+        assert_eq!(general.recognizes(message), dfa.matches(message));
+        assert_eq!(earley.recognizes(message), compiled.recognizes(message));
+    }
+    assert_eq!(
+        12,
+        messages.iter().filter(|x| compiled.recognizes(x)).count()
+    );
+
+    let regular = rewritten.regular_rules().unwrap();
+    assert!(regular.contains("42") && regular.contains("31"));
+    assert!(!regular.contains("0") && !regular.contains("8") && !regular.contains("11"));
+    assert!(matches!(Matcher::new(&rewritten), Ok(Matcher::General(_))));
+    assert_eq!(Ok(None), rewritten.regex());
+
+    // minimized: a start state that accepts, and one that's given up
+    let stars = grammar_of("0: \"a\"* | (\"a\" \"a\")* | \"\"\n");
+    let dfa = Dfa::new(&stars, &stars.rules["0"]);
+    assert_eq!(2, dfa.accepting.len());
+    assert_eq!(vec![false, true], dfa.dead);
+    let same = grammar_of("0: (\"a\" | \"b\") \"c\" | [ab] \"c\"\n");
+    assert_eq!(4, Dfa::new(&same, &same.rules["0"]).accepting.len());
+    let plus = grammar_of("0: \"a\"+\n");
+    assert_eq!(
+        vec![2, 3],
+        Dfa::new(&plus, &plus.rules["0"]).match_ends("xaab", 1)
+    );
+
+    // regular rules that match nothing still work inside Earley
+    let nullable = grammar_of("0: 1 0 | \"x\"\n1: \"a\"? [bé]*\n");
+    let compiled = Earley::compiled(&nullable, "0").unwrap();
+    for message in ["x", "aax", "ébbax", "bx"].iter() {
+        assert!(compiled.recognizes(message));
+    }
+    assert!(!compiled.recognizes("aa"));
+    assert!(!compiled.recognizes("cx"));
+
+    let escaped = grammar_of("0: 1+ [^a-c\\]] | \"a.b\"?\n1: \"x\" | \"yz\"\n");
+    assert_eq!(
+        Ok(Some(r"^(?:(?:x|yz)+[^a-c\]]|(?:a\.b)?)$".to_string())),
+        escaped.regex()
+    );
+    let broken = grammar_of("0: 1\n1: 2\n");
+    assert_eq!(
+        Some(GrammarParseError::UnknownRule("2".to_string())),
+        broken.regex().err()
+    );
+}
+
+#[test]
+fn derivation_test() {
+    let grammar = grammar_of("0: 1 2\n1: \"a\"\n2: 1 3 | 3 1\n3: \"b\"\n");
+    let trees = grammar.derivations("aab", 10).unwrap();
+    assert_eq!(1, trees.len());
+    assert_eq!(
+        "0 (alternative 1): \"aab\"\n  1 (alternative 1): \"a\"\n    \"a\"\n  \
+         2 (alternative 1): \"ab\"\n    1 (alternative 1): \"a\"\n      \"a\"\n    \
+         3 (alternative 1): \"b\"\n      \"b\"\n",
+        trees[0].indented("aab")
+    );
+    let trees = grammar.derivations("aba", 10).unwrap();
+    assert!(matches!(&trees[0], Tree::Rule { children, .. }
+        if matches!(&children[1], Tree::Rule { alternative: 1, .. })));
+    assert!(grammar.derivations("abb", 10).unwrap().is_empty());
+
+    // every way of bracketing the message
+    let ambiguous = grammar_of("0: 0 0 | \"a\"\n");
+    assert_eq!(2, ambiguous.derivations("aaa", 10).unwrap().len());
+    assert_eq!(5, ambiguous.derivations("aaaa", 10).unwrap().len());
+    assert_eq!(3, ambiguous.derivations("aaaa", 3).unwrap().len());
+    let (trees, more) = ambiguous.first_derivations(&"a".repeat(20), 20).unwrap();
+    assert_eq!((20, true), (trees.len(), more));
+    assert_eq!(
+        "Showing the first 20 of more than 20 derivations",
+        found(trees.len(), more)
+    );
+    let (trees, more) = ambiguous.first_derivations("aaaa", 5).unwrap();
+    assert_eq!((5, false), (trees.len(), more));
+    let looping = grammar_of("0: 0 | \"a\"\n");
+    assert_eq!(1, looping.derivations("a", 10).unwrap().len());
+
+    let graph = dot(&ambiguous.derivations("aaa", 10).unwrap(), "aaa", false);
+    assert!(graph.starts_with("digraph derivations {\n  label=\"2 derivation(s) found\";\n"));
+    assert_eq!(2, graph.matches("subgraph cluster_").count());
+    assert_eq!(14, graph.matches(" -> ").count());
+    assert!(graph.contains("d1n0 [label=\"0 #1\\n\\\"aaa\\\"\"];"));
+}
+
+/// The grammar as read, or its rewrite if `rest` is just 'rewritten'.
+fn chosen_rules(grammar: Grammar, rest: &[&str]) -> Result<Grammar, TopLevelError> {
+    match rest {
+        [] => Ok(grammar),
+        ["rewritten"] if grammar.can_rewrite() => Ok(grammar.rewrite()),
+        ["rewritten"] => Err(TopLevelError::BadUsage(
+            "only rules with an 8, 11, 31 and 42 can be rewritten".to_string(),
+        )),
+        _ => Err(TopLevelError::BadUsage(format!(
+            "unexpected arguments {:?}",
+            rest
+        ))),
+    }
 }
 
 fn main() -> Result<(), TopLevelError> {
     let filename = env::args().nth(1).expect("No file argument given.");
     let contents = fs::read_to_string(filename)?;
-    let mut grammar = Grammar::new();
     let mut lines = contents.lines();
+    let grammar = Grammar::read(&mut lines)?;
 
-    for line in &mut lines {
-        if line == "" {
-            break;
+    // 'tree' or 'dot' and a message show how it matches, using the
+    // rewritten rules if 'rewritten' comes after it, and then optionally how
+    // many derivations to show; 'regex' prints the start rule as a regular
+    // expression, if it can be written as one
+    let args: Vec<String> = env::args().skip(2).collect();
+    let args: Vec<&str> = args.iter().map(|x| x.as_str()).collect();
+    if let ["regex", rest @ ..] = args.as_slice() {
+        let grammar = chosen_rules(grammar, rest)?;
+        match grammar.regex()? {
+            Some(regex) => println!("{}", regex),
+            None => println!("Rule {} is recursive, so it isn't regular.", grammar.start),
         }
+        return Ok(());
+    }
 
-        let (num, rule) = Rule::new(line)?;
-        grammar.add_rule(num, rule)?;
+    if let [mode, message, rest @ ..] = args.as_slice() {
+        let (limit, rest) = match rest.split_last() {
+            Some((last, before)) if last.parse::<usize>().is_ok() => {
+                (usize::from_str(last)?, before)
+            }
+            _ => (DERIVATION_LIMIT, rest),
+        };
+        let grammar = chosen_rules(grammar, rest)?;
+        let (trees, more) = grammar.first_derivations(message, limit)?;
+        match *mode {
+            "tree" => {
+                println!("{}.", found(trees.len(), more));
+                for (idx, tree) in trees.iter().enumerate() {
+                    println!("Derivation {}:", idx + 1);
+                    print!("{}", tree.indented(message));
+                }
+            }
+            "dot" => print!("{}", dot(&trees, message, more)),
+            _ => {
+                return Err(TopLevelError::BadUsage(format!(
+                    "unknown mode {}; try tree or dot",
+                    mode
+                )))
+            }
+        }
+        return Ok(());
+    }
+    if !args.is_empty() {
+        return Err(TopLevelError::BadUsage(format!(
+            "unexpected arguments {:?}; try tree MESSAGE, dot MESSAGE or regex",
+            args
+        )));
     }
 
     let mut matched_orig_lines = 0;
+    let matcher = Matcher::new(&grammar)?;
+
+    if !grammar.can_rewrite() {
+        for line in &mut lines {
+            let orig = run_parser!(matcher, line, matched_orig_lines);
+            println!("{} ==> {}", line, orig);
+        }
+        println!("{} lines matched.", matched_orig_lines);
+        return Ok(());
+    }
+
     let mut matched_rewritten_lines = 0;
-    let rewritten_grammar = grammar.rewrite();
+    let rewritten_matcher = Matcher::new(&grammar.rewrite())?;
 
     for line in &mut lines {
-        let orig = run_parser!(grammar, line, matched_orig_lines);
-        let rewritten = run_parser!(rewritten_grammar, line, matched_rewritten_lines);
+        let orig = run_parser!(matcher, line, matched_orig_lines);
+        let rewritten = run_parser!(rewritten_matcher, line, matched_rewritten_lines);
         println!("{} ==> {} / {}", line, orig, rewritten);
     }
 
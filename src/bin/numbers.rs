@@ -1,108 +1,434 @@
-use advent2020::errors::TopLevelError;
-use std::collections::HashMap;
-struct Game {
-    history: HashMap<usize, History>,
-    on_turn: usize,
-    last_value: usize,
-}
+use advent2020::errors::{MemoryGameError, TopLevelError};
+use std::collections::{BTreeMap, HashMap};
+use std::env;
+use std::fmt;
+use std::fs;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+// Values below this get a slot in an array, and only the rarer, bigger ones
+// go in a hash map. Slots are four bytes, so this caps the array at 1GB.
+const DEFAULT_DENSE_LIMIT: usize = 1 << 28;
+
+// How many of the smallest values get their first appearance reported.
+const DEFAULT_FIRST_SEEN: usize = 10;
 
-pub enum History {
-    New(usize),
-    Old(usize, usize),
+// Slots hold the turn plus one, so that zero can mean "never".
+const MAX_TURNS: usize = u32::MAX as usize;
+
+/// The last turn each value was spoken on. Every value after the starting
+/// ones is a gap between two turns, so values and turns both fit in a `u32`.
+/// The hash map has at most one entry per value at or above the dense limit,
+/// and no value can be bigger than the final turn, so it never holds more
+/// than `final_turn - dense_limit` entries of eight bytes each, plus the
+/// map's own overhead. With the default limit that's nothing at all until a
+/// game goes past 2^28 turns, and at most about 4G entries at `MAX_TURNS`.
+pub struct History {
+    dense: Vec<u32>,
+    dense_limit: usize,
+    sparse: HashMap<u32, u32>,
 }
 
 impl History {
-    fn add_timestamp(&mut self, now: usize) {
-        match self {
-            History::New(first) => *self = History::Old(*first, now),
-            History::Old(_, earlier) => *self = History::Old(*earlier, now),
+    fn new(dense_limit: usize) -> History {
+        History {
+            dense: Vec::new(),
+            dense_limit,
+            sparse: HashMap::new(),
         }
     }
-}
 
-impl Game {
-    fn new(starting_values: &[usize]) -> Result<Game, TopLevelError> {
-        if starting_values.is_empty() {
-            return Err(TopLevelError::NoInputFound);
+    /// Makes array slots for every value below `values`, as far as the limit
+    /// allows, moving anything already in the hash map across.
+    fn reserve(&mut self, values: usize) {
+        let size = values.min(self.dense_limit);
+        if self.dense.len() >= size {
+            return;
         }
 
-        let mut history = HashMap::new();
-        let mut on_turn = 0;
-        let mut last_value = 0;
+        self.dense.resize(size, 0);
+        let dense = &mut self.dense;
+        self.sparse.retain(|value, turn| {
+            if (*value as usize) < size {
+                dense[*value as usize] = *turn + 1;
+                false
+            } else {
+                true
+            }
+        });
+    }
 
-        for value in starting_values {
-            match history.get_mut(value) {
-                None => {
-                    let _ = history.insert(*value, History::New(on_turn));
-                }
-                Some(v) => v.add_timestamp(on_turn),
+    fn last_seen(&self, value: usize) -> Option<usize> {
+        match self.dense.get(value) {
+            Some(slot) => slot.checked_sub(1).map(|x| x as usize),
+            None => self.sparse.get(&(value as u32)).map(|x| *x as usize),
+        }
+    }
+
+    /// Records `value` as spoken on `turn`, returning the turn it was last
+    /// spoken on before that.
+    fn replace(&mut self, value: usize, turn: usize) -> Option<usize> {
+        match self.dense.get_mut(value) {
+            Some(slot) => {
+                let previous = std::mem::replace(slot, (turn + 1) as u32);
+                previous.checked_sub(1).map(|x| x as usize)
             }
-            on_turn += 1;
-            last_value = *value;
+            None => self
+                .sparse
+                .insert(value as u32, turn as u32)
+                .map(|x| x as usize),
         }
+    }
+}
 
-        Ok(Game {
-            history,
-            on_turn,
-            last_value,
-        })
+struct Game {
+    // every value spoken so far except the last one, which is only
+    // recorded once the next turn needs to know about it
+    history: History,
+    on_turn: usize,
+    last_value: usize,
+}
+
+impl Game {
+    fn new(starting_values: &[usize]) -> Result<Game, MemoryGameError> {
+        Game::with_dense_limit(starting_values, DEFAULT_DENSE_LIMIT)
     }
 
-    fn add_to_history(&mut self, value: usize) {
-        match self.history.get_mut(&value) {
-            None => {
-                let _ = self.history.insert(value, History::New(self.on_turn));
-            }
-            Some(v) => v.add_timestamp(self.on_turn),
+    fn with_dense_limit(
+        starting_values: &[usize],
+        dense_limit: usize,
+    ) -> Result<Game, MemoryGameError> {
+        let (last_value, earlier) = starting_values
+            .split_last()
+            .ok_or(MemoryGameError::NoStartingNumbers)?;
+        if let Some(value) = starting_values.iter().find(|x| **x > u32::MAX as usize) {
+            return Err(MemoryGameError::ValueTooLarge(*value));
         }
+        let mut history = History::new(dense_limit);
+
+        history.reserve(starting_values.len());
+        for (turn, value) in earlier.iter().enumerate() {
+            history.replace(*value, turn);
+        }
+
+        Ok(Game {
+            history,
+            on_turn: starting_values.len(),
+            last_value: *last_value,
+        })
     }
 
+    /// Speaks the next number. Only good for `MAX_TURNS` turns, which
+    /// `run_through_turn` checks.
     fn step(&mut self) {
-        let insert_history = self
-            .history
-            .get(&self.last_value)
-            .expect("The world broke :(");
-
-        self.last_value = match insert_history {
-            History::New(_) => 0,
-            History::Old(earlier, later) => later - earlier,
-        };
-        self.add_to_history(self.last_value);
+        let previous = self.history.replace(self.last_value, self.on_turn - 1);
+
+        self.last_value = previous.map_or(0, |x| self.on_turn - 1 - x);
         self.on_turn += 1;
     }
 
-    fn run_through_turn(&mut self, final_turn: usize) -> usize {
+    /// If 1 has just been said twice running, the gap is 1 every time from
+    /// now on; that's the only state a step leaves alone.
+    fn stuck_on_one(&self) -> bool {
+        self.last_value == 1
+            && self.on_turn >= 2
+            && self.history.last_seen(1) == Some(self.on_turn - 2)
+    }
+
+    fn run_through_turn(&mut self, final_turn: usize) -> Result<usize, MemoryGameError> {
+        if final_turn > MAX_TURNS {
+            return Err(MemoryGameError::TooManyTurns(final_turn));
+        }
+
+        // nothing said by then can be bigger than the number of turns
+        self.history.reserve(final_turn);
         while self.on_turn < final_turn {
+            if self.stuck_on_one() {
+                self.history.replace(1, final_turn - 2);
+                self.on_turn = final_turn;
+                break;
+            }
             self.step();
         }
 
-        self.last_value
+        Ok(self.last_value)
+    }
+}
+
+/// Reads a comma-separated starting sequence.
+fn parse_sequence(s: &str) -> Result<Vec<usize>, TopLevelError> {
+    let mut result = Vec::new();
+
+    for number in s.trim().split(',').filter(|x| !x.trim().is_empty()) {
+        result.push(usize::from_str(number.trim())?);
+    }
+
+    Ok(result)
+}
+
+/// Calls `visit` with the turn, counting from 1, and the number spoken on
+/// it, for every turn through `final_turn`, starting numbers included.
+fn each_spoken<F: FnMut(usize, usize)>(
+    starting_values: &[usize],
+    final_turn: usize,
+    mut visit: F,
+) -> Result<(), MemoryGameError> {
+    if final_turn > MAX_TURNS {
+        return Err(MemoryGameError::TooManyTurns(final_turn));
+    }
+    let mut game = Game::new(starting_values)?;
+
+    game.history.reserve(final_turn);
+    for (turn, value) in starting_values.iter().enumerate().take(final_turn) {
+        visit(turn + 1, *value);
+    }
+    while game.on_turn < final_turn {
+        game.step();
+        visit(game.on_turn, game.last_value);
+    }
+
+    Ok(())
+}
+
+/// A summary of a game up to some turn.
+#[derive(Debug, PartialEq)]
+struct Analysis {
+    turns: usize,
+    zeros: usize,
+    /// The biggest number spoken after the starting ones, which is the
+    /// longest any number went unsaid, and the turn it was spoken on.
+    largest_gap: Option<(usize, usize)>,
+    /// The turn each of the smallest values was first spoken on.
+    first_seen: BTreeMap<usize, usize>,
+}
+
+impl Analysis {
+    fn new(
+        starting_values: &[usize],
+        final_turn: usize,
+        first_seen_below: usize,
+    ) -> Result<Analysis, MemoryGameError> {
+        let mut result = Analysis {
+            turns: 0,
+            zeros: 0,
+            largest_gap: None,
+            first_seen: BTreeMap::new(),
+        };
+
+        each_spoken(starting_values, final_turn, |turn, value| {
+            result.turns = turn;
+            if value == 0 {
+                result.zeros += 1;
+            }
+            if value < first_seen_below {
+                result.first_seen.entry(value).or_insert(turn);
+            }
+            if turn > starting_values.len() && result.largest_gap.is_none_or(|x| value > x.0) {
+                result.largest_gap = Some((value, turn));
+            }
+        })?;
+
+        Ok(result)
+    }
+}
+
+impl fmt::Display for Analysis {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Turns played: {}", self.turns)?;
+        writeln!(
+            f,
+            "0 was spoken {} times ({:.2}%)",
+            self.zeros,
+            100.0 * self.zeros as f64 / self.turns.max(1) as f64
+        )?;
+        if let Some((gap, turn)) = self.largest_gap {
+            writeln!(
+                f,
+                "Largest gap: {} turns, for the number spoken on turn {}",
+                gap,
+                turn - 1
+            )?;
+        }
+        writeln!(f, "First appearances:")?;
+        for (value, turn) in self.first_seen.iter() {
+            writeln!(f, "  {}: turn {}", value, turn)?;
+        }
+        Ok(())
     }
 }
 
 #[test]
 fn game_tests() {
     let mut test1 = Game::new(&[0, 3, 6]).unwrap();
-    assert_eq!(0, test1.run_through_turn(10));
+    assert_eq!(0, test1.run_through_turn(10).unwrap());
     let mut test2 = Game::new(&[1, 3, 2]).unwrap();
-    assert_eq!(1, test2.run_through_turn(2020));
+    assert_eq!(1, test2.run_through_turn(2020).unwrap());
     let mut test3 = Game::new(&[2, 1, 3]).unwrap();
-    assert_eq!(10, test3.run_through_turn(2020));
+    assert_eq!(10, test3.run_through_turn(2020).unwrap());
     let mut test4 = Game::new(&[1, 2, 3]).unwrap();
-    assert_eq!(27, test4.run_through_turn(2020));
+    assert_eq!(27, test4.run_through_turn(2020).unwrap());
     let mut test5 = Game::new(&[2, 3, 1]).unwrap();
-    assert_eq!(78, test5.run_through_turn(2020));
+    assert_eq!(78, test5.run_through_turn(2020).unwrap());
     let mut test6 = Game::new(&[3, 2, 1]).unwrap();
-    assert_eq!(438, test6.run_through_turn(2020));
+    assert_eq!(438, test6.run_through_turn(2020).unwrap());
     let mut test7 = Game::new(&[3, 1, 2]).unwrap();
-    assert_eq!(1836, test7.run_through_turn(2020));
+    assert_eq!(1836, test7.run_through_turn(2020).unwrap());
+}
+
+#[test]
+fn engine_tests() {
+    // the array, the hash map, and values moving from one to the other
+    let mut dense = Game::new(&[0, 3, 16]).unwrap();
+    let mut sparse = Game::with_dense_limit(&[0, 3, 16], 0).unwrap();
+    let mut mixed = Game::with_dense_limit(&[0, 3, 16], 100).unwrap();
+    for turn in [10, 50, 2020, 5000].iter() {
+        let expected = sparse.run_through_turn(*turn).unwrap();
+        assert_eq!(expected, dense.run_through_turn(*turn).unwrap());
+        assert_eq!(expected, mixed.run_through_turn(*turn).unwrap());
+    }
+
+    // once 1 comes up twice running, it's 1 forever
+    let mut stepped = Game::new(&[2, 1, 1]).unwrap();
+    for _ in 0..20 {
+        stepped.step();
+    }
+    assert_eq!(1, stepped.last_value);
+    let mut stuck = Game::with_dense_limit(&[2, 1, 1], 16).unwrap();
+    assert_eq!(1, stuck.run_through_turn(23).unwrap());
+    assert_eq!(stepped.on_turn, stuck.on_turn);
+    assert_eq!(stepped.history.last_seen(1), stuck.history.last_seen(1));
+    assert_eq!(1, stuck.run_through_turn(4_000_000_000).unwrap());
+
+    assert_eq!(
+        Some(MemoryGameError::TooManyTurns(MAX_TURNS + 1)),
+        stuck.run_through_turn(MAX_TURNS + 1).err()
+    );
+    assert_eq!(
+        Some(MemoryGameError::NoStartingNumbers),
+        Game::new(&[]).err()
+    );
+    assert_eq!(
+        Some(MemoryGameError::ValueTooLarge(1 << 32)),
+        Game::new(&[0, 1 << 32]).err()
+    );
+}
+
+#[test]
+fn sparse_history() {
+    // a tiny array, so almost everything lands in the hash map
+    let mut dense = Game::new(&[16, 1, 0, 18, 12, 14, 19]).unwrap();
+    let mut sparse = Game::with_dense_limit(&[16, 1, 0, 18, 12, 14, 19], 64).unwrap();
+    assert_eq!(
+        dense.run_through_turn(1_000_000).unwrap(),
+        sparse.run_through_turn(1_000_000).unwrap()
+    );
+    assert_eq!(64, sparse.history.dense.len());
+    assert!(sparse.history.sparse.len() > 100_000);
+    assert!(sparse.history.sparse.keys().all(|x| *x >= 64));
+    for value in (0..5000).step_by(7) {
+        assert_eq!(
+            dense.history.last_seen(value),
+            sparse.history.last_seen(value)
+        );
+    }
+}
+
+#[test]
+fn sequence_tests() {
+    assert_eq!(vec![0, 3, 6], parse_sequence("0,3,6\n").unwrap());
+    assert_eq!(
+        vec![16, 1, 0, 18, 12, 14, 19],
+        parse_sequence(&fs::read_to_string("inputs/day15.txt").unwrap()).unwrap()
+    );
+    assert!(parse_sequence("0,x").is_err());
+
+    let mut spoken = Vec::new();
+    each_spoken(&[0, 3, 6], 10, |turn, value| spoken.push((turn, value))).unwrap();
+    assert_eq!(
+        vec![0, 3, 6, 0, 3, 3, 1, 0, 4, 0],
+        spoken.iter().map(|x| x.1).collect::<Vec<usize>>()
+    );
+    assert_eq!(
+        (1..=10).collect::<Vec<usize>>(),
+        spoken.iter().map(|x| x.0).collect::<Vec<usize>>()
+    );
+
+    let analysis = Analysis::new(&[0, 3, 6], 10, 5).unwrap();
+    assert_eq!(10, analysis.turns);
+    assert_eq!(4, analysis.zeros);
+    assert_eq!(Some((4, 9)), analysis.largest_gap);
+    assert_eq!(
+        vec![(0, 1), (1, 7), (3, 2), (4, 9)],
+        analysis
+            .first_seen
+            .into_iter()
+            .collect::<Vec<(usize, usize)>>()
+    );
 }
 
 fn main() -> Result<(), TopLevelError> {
-    let mut game = Game::new(&[16, 1, 0, 18, 12, 14, 19])?;
-    let result1 = game.run_through_turn(2020);
-    println!("The 2020th number spoken is {}", result1);
-    let result2 = game.run_through_turn(30000000);
-    println!("The 2020th number spoken is {}", result2);
+    // a starting sequence or a file holding one, then optionally a turn,
+    // then optionally 'stream' or 'analyse' and how many values to report
+    let input = env::args().nth(1).expect("No starting sequence given.");
+    let contents = if Path::new(&input).exists() {
+        fs::read_to_string(&input)?
+    } else {
+        input
+    };
+    let starting_values = parse_sequence(&contents)?;
+    let mut args: Vec<String> = env::args().skip(2).collect();
+    let turn = match args.first().map(|x| usize::from_str(x)) {
+        Some(Ok(turn)) => {
+            args.remove(0);
+            Some(turn)
+        }
+        _ => None,
+    };
+
+    let args: Vec<&str> = args.iter().map(|x| x.as_str()).collect();
+    match (args.as_slice(), turn) {
+        (["stream"], turn) => {
+            let stdout = io::stdout();
+            let mut out = BufWriter::new(stdout.lock());
+            let mut result = Ok(());
+            each_spoken(&starting_values, turn.unwrap_or(2020), |_, value| {
+                if result.is_ok() {
+                    result = writeln!(out, "{}", value);
+                }
+            })?;
+            result?;
+            out.flush()?;
+        }
+        (["analyse", rest @ ..], turn) => {
+            let first_seen = match rest.first() {
+                Some(count) => usize::from_str(count)?,
+                None => DEFAULT_FIRST_SEEN,
+            };
+            print!(
+                "{}",
+                Analysis::new(&starting_values, turn.unwrap_or(2020), first_seen)?
+            );
+        }
+        ([], Some(turn)) => {
+            let mut game = Game::new(&starting_values)?;
+            let result = game.run_through_turn(turn)?;
+            println!("The {}th number spoken is {}", turn, result);
+        }
+        ([], None) => {
+            let mut game = Game::new(&starting_values)?;
+            let result1 = game.run_through_turn(2020)?;
+            println!("The 2020th number spoken is {}", result1);
+            let result2 = game.run_through_turn(30000000)?;
+            println!("The 30000000th number spoken is {}", result2);
+        }
+        (other, _) => {
+            return Err(TopLevelError::BadUsage(format!(
+                "unknown mode {:?}; try stream or analyse",
+                other
+            )))
+        }
+    }
+
     Ok(())
 }
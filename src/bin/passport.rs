@@ -21,7 +21,7 @@ macro_rules! valid_range {
     ($str: expr, $low: expr, $high: expr) => {
         match u64::from_str($str) {
             Err(_) => return false,
-            Ok(x) if x < $low || x > $high => return false,
+            Ok(x) if !($low..=$high).contains(&x) => return false,
             Ok(_) => {}
         }
     };
@@ -108,7 +108,7 @@ impl Passport {
             None => return false,
             Some(ref x) if x.len() != 7 => return false,
             Some(ref x) => {
-                if !x.starts_with('#') || x[1..].chars().any(|x| !x.is_digit(16)) {
+                if !x.starts_with('#') || x[1..].chars().any(|x| !x.is_ascii_hexdigit()) {
                     return false;
                 }
             }
@@ -124,7 +124,7 @@ impl Passport {
         // check the passport number
         match self.passport_id {
             None => false,
-            Some(ref x) => x.len() == 9 && x.chars().all(|x| x.is_digit(10)),
+            Some(ref x) => x.len() == 9 && x.chars().all(|x| x.is_ascii_digit()),
         }
     }
 }
@@ -145,7 +145,7 @@ fn main() -> Result<(), TopLevelError> {
         let mut current_passport = Passport::new();
 
         for line in contents.lines() {
-            if line == "" {
+            if line.is_empty() {
                 passports.push(current_passport);
                 current_passport = Passport::new();
             } else {
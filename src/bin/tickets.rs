@@ -53,7 +53,7 @@ fn parse_fields<'a, I: Iterator<Item = &'a str>>(
     let mut res = Vec::new();
 
     for x in iterator {
-        if x == "" {
+        if x.is_empty() {
             return Ok(res);
         } else {
             res.push(Field::new(x)?)
@@ -106,7 +106,7 @@ fn parse_my_ticket<'a, I: Iterator<Item = &'a str>>(
     let suffix = iterator
         .next()
         .ok_or(TicketParseError::YourTicketParseError)?;
-    if suffix != "" {
+    if !suffix.is_empty() {
         return Err(TicketParseError::YourTicketParseError);
     }
 
@@ -155,7 +155,7 @@ fn resolve_theories(mut theories: Vec<Vec<Field>>) -> Result<Vec<Field>, TopLeve
         for theory in theories.iter_mut() {
             if theory.len() > 1 {
                 let old_len = theory.len();
-                theory.retain(|v| !singletons.contains(&v));
+                theory.retain(|v| !singletons.contains(v));
                 changed_something |= old_len != theory.len();
             }
         }
@@ -189,7 +189,7 @@ fn main() -> Result<(), TopLevelError> {
 
     println!("Ticket scanning error rate: {}", sum);
 
-    let mut theories = Vec::with_capacity((&my_ticket.0).len());
+    let mut theories = Vec::with_capacity(my_ticket.0.len());
     theories.resize(my_ticket.0.len(), fields);
     valid_tickets.push(my_ticket.clone());
     println!("There are {} valid tickets", valid_tickets.len());
@@ -201,7 +201,7 @@ fn main() -> Result<(), TopLevelError> {
     }
 
     let resolved_theories = resolve_theories(theories)?;
-    assert_eq!(resolved_theories.len(), (&my_ticket.0).len());
+    assert_eq!(resolved_theories.len(), my_ticket.0.len());
 
     let mut departure_product = 1;
 
@@ -0,0 +1,29 @@
+use advent2020::errors::TopLevelError;
+use advent2020::jigsaw::{read_image, render, Generator};
+use std::env;
+use std::fs;
+use std::str::FromStr;
+
+// tile_generator SEED TILE_SIZE (IMAGE_FILE | WIDTHxHEIGHT) [unique]
+//
+// Cuts the image in IMAGE_FILE, or a random one WIDTH by HEIGHT tiles big,
+// into scrambled tiles and prints them in the day 20 input format.
+fn main() -> Result<(), TopLevelError> {
+    let seed = u64::from_str(&env::args().nth(1).expect("No seed argument given."))?;
+    let edge_length = usize::from_str(&env::args().nth(2).expect("No tile size given."))?;
+    let source = env::args().nth(3).expect("No image or dimensions given.");
+    let unique = env::args().nth(4).as_deref() == Some("unique");
+    let mut generator = Generator::new(seed, edge_length)?.unique_edges(unique);
+
+    let dimensions: Vec<Result<usize, _>> = source.split('x').map(usize::from_str).collect();
+    let tiles = match dimensions.as_slice() {
+        [Ok(width), Ok(height)] => generator.generate(*width, *height)?,
+        _ => {
+            let image = read_image(&fs::read_to_string(source)?)?;
+            generator.cut(&image)?
+        }
+    };
+
+    print!("{}", render(&tiles));
+    Ok(())
+}
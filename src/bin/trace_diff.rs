@@ -0,0 +1,45 @@
+use advent2020::errors::TopLevelError;
+use advent2020::machine::{diverge, read_trace, TraceEntry};
+use std::env;
+use std::fs::File;
+use std::io::BufReader;
+
+const CONTEXT: usize = 3;
+
+fn show(prefix: &str, entry: Option<&TraceEntry>) {
+    match entry {
+        None => println!("{} <trace ended>", prefix),
+        Some(entry) => println!(
+            "{} {:6}: {:04}: {:12} acc = {}",
+            prefix,
+            entry.step,
+            entry.location,
+            entry.instruction.to_string(),
+            entry.accumulator
+        ),
+    }
+}
+
+fn main() -> Result<(), TopLevelError> {
+    let left_file = env::args().nth(1).expect("No first trace argument given.");
+    let right_file = env::args().nth(2).expect("No second trace argument given.");
+    let left = read_trace(BufReader::new(File::open(&left_file)?))?;
+    let right = read_trace(BufReader::new(File::open(&right_file)?))?;
+
+    println!("{}: {} steps", left_file, left.len());
+    println!("{}: {} steps", right_file, right.len());
+
+    match diverge(&left, &right) {
+        None => println!("The traces are identical."),
+        Some(divergence) => {
+            println!("The traces diverge at step {}:", divergence.step);
+            for entry in left[divergence.step.saturating_sub(CONTEXT)..divergence.step].iter() {
+                show(" ", Some(entry));
+            }
+            show("<", divergence.left);
+            show(">", divergence.right);
+        }
+    }
+
+    Ok(())
+}
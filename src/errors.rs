@@ -13,6 +13,8 @@ pub enum TopLevelError {
     NoInputFound,
     #[error("No solution found")]
     NoSolutionFound,
+    #[error("Bad usage: {0}")]
+    BadUsage(String),
     #[error("Unknown error occurred")]
     UnknownError,
     #[error("Failed to parse passport: {source}")]
@@ -40,6 +42,10 @@ pub enum TopLevelError {
         #[from]
         source: ExecutionError,
     },
+    #[error("Assembly error: {0}")]
+    AssemblyError(#[from] AssemblyError),
+    #[error("Trace error: {0}")]
+    TraceError(#[from] TraceError),
     #[error("Numeric conversion error: {0}")]
     NumConversionError(#[from] ParseIntError),
     #[error("Error parsing map: {0}")]
@@ -50,6 +56,8 @@ pub enum TopLevelError {
     IllegalFerryCommand(#[from] IllegalFerryCommand),
     #[error("Mask parsing error: {0}")]
     MaskParseError(#[from] MaskParseError),
+    #[error("Docking computer error: {0}")]
+    ComputerError(#[from] ComputerError),
     #[error("Bitmask command parsing error: {0}")]
     BitmaskCommandParseError(#[from] BitmaskCommandParseError),
     #[error("Ticket parsing error: {0}")]
@@ -58,8 +66,18 @@ pub enum TopLevelError {
     GrammarParseError(#[from] GrammarParseError),
     #[error("Bad tile parse: {0}")]
     TileParseError(#[from] TileParseError),
+    #[error("Jigsaw error: {0}")]
+    JigsawError(#[from] JigsawError),
+    #[error("Bad pattern: {0}")]
+    PatternParseError(#[from] PatternParseError),
     #[error("Error parsing directions: {0}")]
     DirectionParseError(#[from] DirectionParseError),
+    #[error("Combat error: {0}")]
+    CombatError(#[from] CombatError),
+    #[error("Crab cups error: {0}")]
+    CupError(#[from] CupError),
+    #[error("Memory game error: {0}")]
+    MemoryGameError(#[from] MemoryGameError),
 }
 
 #[derive(Error, Debug)]
@@ -141,12 +159,84 @@ pub enum InstructionParseError {
     EmptyInstruction,
     #[error("Instruction '{0}' missing an operand")]
     MissingOperand(String),
+    #[error("Instruction '{0}' takes {1} operands, but was given {2}")]
+    WrongOperandCount(String, usize, usize),
+    #[error("Instruction '{0}' needs a register, but was given {1}")]
+    ExpectedRegister(String, String),
 }
 
 #[derive(Error, Debug)]
 pub enum ExecutionError {
     #[error("Tried to execute non-existent instruction at {0}")]
     NonExistentLocation(isize),
+    #[error("Division by zero at {0}")]
+    DivisionByZero(isize),
+    #[error("Ran out of input at {0}")]
+    InputExhausted(isize),
+    #[error("Arithmetic overflow at {0}")]
+    Overflow(isize),
+}
+
+#[derive(Error, Debug)]
+pub enum AssemblyError {
+    #[error("Line {0}: bad label '{1}'")]
+    BadLabel(usize, String),
+    #[error("Line {0}: label '{1}' defined twice")]
+    DuplicateLabel(usize, String),
+    #[error("Line {0}: unknown directive {1}")]
+    UnknownDirective(usize, String),
+    #[error("Line {0}: malformed directive '{1}'")]
+    BadDirective(usize, String),
+    #[error("Line {0}: bad value '{1}'")]
+    BadValue(usize, String),
+    #[error("Line {0}: {1}")]
+    BadInstruction(usize, InstructionParseError),
+}
+
+#[derive(Error, Debug, PartialEq)]
+pub enum DebugCommandParseError {
+    #[error("Got an empty command?")]
+    EmptyCommand,
+    #[error("Unknown command: {0}")]
+    UnknownCommand(String),
+    #[error("Bad watch expression: {0}")]
+    BadWatch(String),
+    #[error("Bad number: {0}")]
+    BadNumber(#[from] ParseIntError),
+}
+
+#[derive(Error, Debug)]
+pub enum TraceParseError {
+    #[error("Error parsing trace entry: {0}")]
+    NomError(String),
+    #[error("Bad instruction in trace: {0}")]
+    InstructionParseError(#[from] InstructionParseError),
+    #[error("Bad number in trace: {0}")]
+    NumConversionError(#[from] ParseIntError),
+    #[error("Unexpected text after trace entry: {0}")]
+    TrailingGarbage(String),
+}
+
+impl<'a> From<nom::Err<nom::error::Error<&'a str>>> for TraceParseError {
+    fn from(x: nom::Err<nom::error::Error<&'a str>>) -> TraceParseError {
+        match x {
+            nom::Err::Incomplete(x) => {
+                TraceParseError::NomError(format!("Incomplete data stream (need: {:?})", x))
+            }
+            nom::Err::Error(e) => TraceParseError::NomError(e.to_string()),
+            nom::Err::Failure(e) => TraceParseError::NomError(e.to_string()),
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum TraceError {
+    #[error("IO error handling trace: {0}")]
+    IOError(#[from] io::Error),
+    #[error("Error executing machine: {0}")]
+    ExecutionError(#[from] ExecutionError),
+    #[error("Bad trace entry on line {0}: {1}")]
+    BadLine(usize, TraceParseError),
 }
 
 #[derive(Error, Debug)]
@@ -169,12 +259,22 @@ pub enum IllegalFerryCommand {
 
 #[derive(Error, Debug)]
 pub enum MaskParseError {
-    #[error("Mask value is the wrong size; expected 36 characters, got {0}")]
-    WrongLength(usize),
+    #[error("Mask value is too long; expected at most 128 bits, got {0}")]
+    TooLong(usize),
+    #[error("Mask has no digits")]
+    Empty,
     #[error("Unexpected character '{0}'")]
     UnexpectedCharacter(char),
 }
 
+#[derive(Error, Debug)]
+pub enum ComputerError {
+    #[error("Mask is {0} bits, but the word is only {1} bits")]
+    WiderThanWord(u32, u32),
+    #[error("Word width must be between 8 and 128 bits, not {0}")]
+    UnsupportedWidth(u32),
+}
+
 #[derive(Error, Debug)]
 pub enum BitmaskCommandParseError {
     #[error("Got an empty command?")]
@@ -205,14 +305,20 @@ pub enum TicketParseError {
 
 #[derive(Error, Debug, PartialEq)]
 pub enum GrammarParseError {
-    #[error("Bad rule reference: {0}")]
-    ParseIntError(#[from] ParseIntError),
     #[error("Reference to unknown rule: {0}")]
-    UnknownRule(usize),
-    #[error("Bad rule definition: {0}")]
-    BadRule(String),
+    UnknownRule(String),
     #[error("Duplicate rule definition for {0}")]
-    DuplicateRule(usize),
+    DuplicateRule(String),
+    #[error("Unexpected '{0}' at column {1}")]
+    UnexpectedCharacter(char, usize),
+    #[error("Rule ends too soon at column {0}")]
+    UnexpectedEnd(usize),
+    #[error("No closing match for '{0}' at column {1}")]
+    Unterminated(char, usize),
+    #[error("Backwards character range {0}-{1}")]
+    BadRange(char, char),
+    #[error("Line {0}: {1}")]
+    AtLine(usize, Box<GrammarParseError>),
 }
 
 #[derive(Error, Debug, PartialEq)]
@@ -225,10 +331,42 @@ pub enum TileParseError {
     IllegalTileIdentifier(#[from] ParseIntError),
     #[error("Illegal tile dimensions for tile {0}")]
     IllegalDimensions(usize),
+    #[error("Tile {0} is only {1} wide; tiles need a border and something inside it")]
+    TooSmall(usize, usize),
     #[error("Weird start to tile: {0}")]
     BadTileStart(String),
 }
 
+#[derive(Error, Debug, PartialEq)]
+pub enum PatternParseError {
+    #[error("Illegal pattern character: '{0}'")]
+    IllegalCharacter(char),
+    #[error("Pattern doesn't require or forbid anything")]
+    EmptyPattern,
+}
+
+#[derive(Error, Debug, PartialEq)]
+pub enum JigsawError {
+    #[error("No tiles to assemble")]
+    NoTiles,
+    #[error("Tile {0} has {1}-pixel edges, but the others have {2}")]
+    MismatchedTile(usize, usize, usize),
+    #[error("Can't make a {1}x{2} board out of {0} tiles")]
+    WrongTileCount(usize, usize, usize),
+    #[error("The tiles don't fit together into a {0}x{1} board; nothing fits at ({2}, {3})")]
+    NoArrangement(usize, usize, usize, usize),
+    #[error("Tiles need at least 3-pixel edges, not {0}")]
+    BadTileSize(usize),
+    #[error("A {0}x{1} image can't be cut into tiles with {2}-pixel insides")]
+    ImageSize(usize, usize, usize),
+    #[error("There aren't enough {0}-pixel edges to make every match unique")]
+    TooFewEdges(usize),
+    #[error("Image row {0} is a different length from the ones before it")]
+    RaggedImage(usize),
+    #[error("Unexpected pixel '{0}' in image")]
+    BadPixel(char),
+}
+
 #[derive(Error, Debug, PartialEq)]
 pub enum DirectionParseError {
     #[error("Invalid base for direction: {0}")]
@@ -238,3 +376,43 @@ pub enum DirectionParseError {
     #[error("Incomplete north/south direction")]
     IncompleteNorthSouthDirection,
 }
+
+#[derive(Error, Debug, PartialEq)]
+pub enum CombatError {
+    #[error("Bad card: {0}")]
+    BadCard(#[from] ParseIntError),
+    #[error("Expected a 'Player N:' line, got: {0}")]
+    BadPlayerLine(String),
+    #[error("Player {0} has more than one deck")]
+    DuplicatePlayer(usize),
+    #[error("Combat needs at least two players, not {0}")]
+    NotEnoughPlayers(usize),
+    #[error("Players {0:?} tied, and these rules have no way to settle it")]
+    UnsettledTie(Vec<usize>),
+    #[error("Not a game event: {0}")]
+    BadEvent(String),
+}
+
+#[derive(Error, Debug, PartialEq)]
+pub enum CupError {
+    #[error("Cup label {0} is used more than once")]
+    DuplicateLabel(String),
+    #[error("Can't pick up {1} cups with only {0} on the table")]
+    TooFewCups(usize, usize),
+    #[error("No cup can be the destination in round {0}")]
+    NoDestination(usize),
+    #[error("There's no cup labelled {0}")]
+    UnknownLabel(String),
+    #[error("Bad checkpoint: {0}")]
+    BadCheckpoint(String),
+}
+
+#[derive(Error, Debug, PartialEq)]
+pub enum MemoryGameError {
+    #[error("The game needs at least one starting number")]
+    NoStartingNumbers,
+    #[error("Can't play through turn {0}; turns are kept in 32 bits")]
+    TooManyTurns(usize),
+    #[error("Can't start with {0}; values are kept in 32 bits")]
+    ValueTooLarge(usize),
+}
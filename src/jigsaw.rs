@@ -0,0 +1,364 @@
+mod assembler;
+mod generator;
+
+pub use crate::jigsaw::assembler::{Ambiguity, Arrangement, Assembler, Side};
+pub use crate::jigsaw::generator::{read_image, render, Generator};
+
+use crate::errors::TileParseError;
+#[cfg(test)]
+use std::collections::HashMap;
+use std::fmt;
+#[cfg(test)]
+use std::fs;
+use std::str::FromStr;
+
+/// A square tile of any size. The edges are read left to right and top to
+/// bottom, so two tiles fit together when the touching edges are equal.
+#[derive(Clone)]
+pub struct Tile {
+    pub identity: usize,
+    pub history: Vec<Modification>,
+    pub top: Vec<bool>,
+    pub bottom: Vec<bool>,
+    pub left: Vec<bool>,
+    pub right: Vec<bool>,
+    pub edge_length: usize,
+    pub raw_data: Vec<bool>,
+}
+
+impl PartialEq for Tile {
+    fn eq(&self, other: &Self) -> bool {
+        self.identity == other.identity && self.raw_data == other.raw_data
+    }
+}
+
+impl fmt::Display for Tile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.identity)?;
+        let mut prefix = ':';
+        for mvmt in self.history.iter() {
+            write!(f, "{}{:?}", prefix, mvmt)?;
+            prefix = '+';
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Modification {
+    FlippedX,
+    FlippedY,
+    Rotated,
+}
+
+impl Tile {
+    pub fn new(
+        identity: usize,
+        history: Vec<Modification>,
+        edge_length: usize,
+        raw_data: Vec<bool>,
+    ) -> Tile {
+        let mut res = Tile {
+            identity,
+            history,
+            top: Vec::with_capacity(edge_length),
+            bottom: Vec::with_capacity(edge_length),
+            left: Vec::with_capacity(edge_length),
+            right: Vec::with_capacity(edge_length),
+            edge_length,
+            raw_data,
+        };
+
+        for i in 0..edge_length {
+            res.top.push(res.get(i, 0));
+            res.bottom.push(res.get(i, edge_length - 1));
+            res.left.push(res.get(0, i));
+            res.right.push(res.get(edge_length - 1, i));
+        }
+
+        res
+    }
+
+    pub fn read<'a, I: Iterator<Item = &'a str>>(
+        lines: &mut I,
+    ) -> Result<Option<Tile>, TileParseError> {
+        match lines.next() {
+            None => Ok(None),
+            Some("") => Tile::read(lines),
+            Some(x) if x.starts_with("Tile ") => {
+                let identity = usize::from_str(&x[5..x.len() - 1])?;
+                let mut edge_length = 0;
+                let mut raw_data = Vec::new();
+
+                for line in lines {
+                    if line.is_empty() {
+                        break;
+                    }
+
+                    for char in line.chars() {
+                        match char {
+                            '.' => raw_data.push(false),
+                            '#' => raw_data.push(true),
+                            _ => return Err(TileParseError::IllegalCharacter(char)),
+                        }
+                    }
+
+                    edge_length += 1;
+                }
+
+                if raw_data.len() != (edge_length * edge_length) {
+                    return Err(TileParseError::IllegalDimensions(identity));
+                }
+                if edge_length < 3 {
+                    return Err(TileParseError::TooSmall(identity, edge_length));
+                }
+
+                Ok(Some(Tile::new(identity, vec![], edge_length, raw_data)))
+            }
+            Some(other) => Err(TileParseError::BadTileStart(other.to_string())),
+        }
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> bool {
+        self.raw_data[(y * self.edge_length) + x]
+    }
+
+    fn set(&mut self, x: usize, y: usize, v: bool) {
+        self.raw_data[(y * self.edge_length) + x] = v;
+    }
+
+    #[cfg(test)]
+    fn draw(&self) {
+        println!("Tile {} [{:?}]:", self.identity, self.history);
+        for y in 0..self.edge_length {
+            for x in 0..self.edge_length {
+                if self.get(x, y) {
+                    print!("#");
+                } else {
+                    print!(".");
+                }
+            }
+            println!();
+        }
+    }
+
+    pub fn flip_over_x(&self) -> Tile {
+        let mut raw_data = Vec::with_capacity(self.edge_length * self.edge_length);
+        let reverser = self.edge_length - 1;
+
+        for y in 0..self.edge_length {
+            for x in 0..self.edge_length {
+                raw_data.push(self.get(x, reverser - y))
+            }
+        }
+
+        let mut new_history = self.history.clone();
+        new_history.push(Modification::FlippedX);
+        Tile::new(self.identity, new_history, self.edge_length, raw_data)
+    }
+
+    pub fn flip_over_y(&self) -> Tile {
+        let mut raw_data = Vec::with_capacity(self.edge_length * self.edge_length);
+        let reverser = self.edge_length - 1;
+
+        for y in 0..self.edge_length {
+            for x in 0..self.edge_length {
+                raw_data.push(self.get(reverser - x, y))
+            }
+        }
+
+        let mut new_history = self.history.clone();
+        new_history.push(Modification::FlippedY);
+        Tile::new(self.identity, new_history, self.edge_length, raw_data)
+    }
+
+    pub fn rotate(&self) -> Tile {
+        let mut res = self.clone();
+
+        res.history.push(Modification::Rotated);
+        for x in 0..self.edge_length {
+            for y in 0..self.edge_length {
+                res.set(self.edge_length - 1 - y, x, self.get(x, y));
+            }
+        }
+
+        Tile::new(res.identity, res.history, res.edge_length, res.raw_data)
+    }
+
+    pub fn variants(self) -> Vec<Tile> {
+        let mut res = vec![];
+        let mut new_elements = vec![self];
+
+        while !new_elements.is_empty() {
+            res.append(&mut new_elements);
+
+            for current in res.iter() {
+                let flip_x = current.flip_over_x();
+                if !res.contains(&flip_x) && !new_elements.contains(&flip_x) {
+                    new_elements.push(flip_x);
+                }
+
+                let flip_y = current.flip_over_y();
+                if !res.contains(&flip_y) && !new_elements.contains(&flip_y) {
+                    new_elements.push(flip_y);
+                }
+
+                let rotated = current.rotate();
+                if !res.contains(&rotated) && !new_elements.contains(&rotated) {
+                    new_elements.push(rotated);
+                }
+            }
+        }
+
+        res
+    }
+
+    pub fn can_be_left_of(&self, other: &Tile) -> bool {
+        self.identity != other.identity && self.right == other.left
+    }
+
+    pub fn can_be_right_of(&self, other: &Tile) -> bool {
+        self.identity != other.identity && self.left == other.right
+    }
+
+    pub fn can_be_above(&self, other: &Tile) -> bool {
+        self.identity != other.identity && self.bottom == other.top
+    }
+
+    pub fn can_be_below(&self, other: &Tile) -> bool {
+        self.identity != other.identity && self.top == other.bottom
+    }
+}
+
+#[test]
+fn flip_x_test() {
+    let raw_data = vec![
+        true, false, true, false, true, true, true, true, false, false, true, true, false, false,
+        false, false,
+    ];
+    let edge_length = 4;
+    let identity = 1;
+    let original = Tile::new(identity, vec![], edge_length, raw_data);
+    let flipped = vec![
+        false, false, false, false, false, false, true, true, true, true, true, true, true, false,
+        true, false,
+    ];
+
+    assert_eq!(flipped, original.flip_over_x().raw_data);
+}
+
+#[test]
+fn flip_y_test() {
+    let raw_data = vec![
+        true, false, true, false, true, true, true, true, false, false, true, true, false, false,
+        false, false,
+    ];
+    let edge_length = 4;
+    let identity = 1;
+    let original = Tile::new(identity, vec![], edge_length, raw_data);
+    let flipped = vec![
+        false, true, false, true, true, true, true, true, true, true, false, false, false, false,
+        false, false,
+    ];
+
+    assert_eq!(flipped, original.flip_over_y().raw_data);
+}
+
+#[test]
+fn rotate_test() {
+    let original = Tile::new(
+        0,
+        vec![],
+        3,
+        vec![true, true, true, false, false, false, true, true, true],
+    );
+    let rotated = vec![true, false, true, true, false, true, true, false, true];
+    assert_eq!(rotated, original.rotate().raw_data);
+}
+
+#[test]
+fn next_to_tests() {
+    let contents = fs::read_to_string("inputs/day20_test.txt").unwrap();
+    let mut lines = contents.lines();
+    let mut tiles = HashMap::new();
+
+    while let Some(new_tile) = Tile::read(&mut lines).unwrap() {
+        tiles.insert(new_tile.identity, new_tile);
+    }
+
+    let tile1951 = tiles.get(&1951).unwrap().flip_over_x();
+    let tile2729 = tiles.get(&2729).unwrap().flip_over_x();
+    let tile2971 = tiles.get(&2971).unwrap().flip_over_x();
+    let tile2311 = tiles.get(&2311).unwrap().flip_over_x();
+    let tile1427 = tiles.get(&1427).unwrap().flip_over_x();
+    let tile1489 = tiles.get(&1489).unwrap().flip_over_x();
+    let tile3079 = tiles.get(&3079).unwrap().clone();
+    let tile2473 = tiles.get(&2473).unwrap().flip_over_y().rotate();
+    let tile1171 = tiles.get(&1171).unwrap().flip_over_y();
+
+    tile1951.draw();
+    println!();
+    tile2729.draw();
+    println!();
+    tile2971.draw();
+    println!();
+    tile2311.draw();
+    println!();
+    tile1427.draw();
+    println!();
+    tile1489.draw();
+    println!();
+    tile3079.draw();
+    println!();
+    tile2473.draw();
+    println!();
+    tile1171.draw();
+    println!();
+
+    // above tests
+    assert!(tile1951.can_be_above(&tile2729));
+    assert!(tile2729.can_be_above(&tile2971));
+    assert!(tile2311.can_be_above(&tile1427));
+    assert!(tile1427.can_be_above(&tile1489));
+    assert!(tile3079.can_be_above(&tile2473));
+    assert!(tile2473.can_be_above(&tile1171));
+
+    // below tests
+    assert!(tile2729.can_be_below(&tile1951));
+    assert!(tile2971.can_be_below(&tile2729));
+    assert!(tile1427.can_be_below(&tile2311));
+    assert!(tile1489.can_be_below(&tile1427));
+    assert!(tile2473.can_be_below(&tile3079));
+    assert!(tile1171.can_be_below(&tile2473));
+
+    // left tests
+    assert!(tile1951.can_be_left_of(&tile2311));
+    assert!(tile2729.can_be_left_of(&tile1427));
+    assert!(tile2971.can_be_left_of(&tile1489));
+    assert!(tile2311.can_be_left_of(&tile3079));
+    assert!(tile1427.can_be_left_of(&tile2473));
+    assert!(tile1489.can_be_left_of(&tile1171));
+
+    // right tests
+    assert!(tile2311.can_be_right_of(&tile1951));
+    assert!(tile1427.can_be_right_of(&tile2729));
+    assert!(tile1489.can_be_right_of(&tile2971));
+    assert!(tile3079.can_be_right_of(&tile2311));
+    assert!(tile2473.can_be_right_of(&tile1427));
+    assert!(tile1171.can_be_right_of(&tile1489));
+}
+
+#[test]
+fn small_tiles() {
+    let mut lines = "Tile 7:\n##\n#.\n".lines();
+    assert!(matches!(
+        Tile::read(&mut lines),
+        Err(TileParseError::TooSmall(7, 2))
+    ));
+    let mut lines = "Tile 8:\n\nTile 9:\n###\n#.#\n###\n".lines();
+    assert!(matches!(
+        Tile::read(&mut lines),
+        Err(TileParseError::TooSmall(8, 0))
+    ));
+    assert_eq!(3, Tile::read(&mut lines).unwrap().unwrap().edge_length);
+}
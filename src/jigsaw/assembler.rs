@@ -0,0 +1,515 @@
+use crate::errors::JigsawError;
+use crate::jigsaw::Tile;
+use std::collections::{HashMap, HashSet};
+
+/// A finished puzzle: `tiles` holds the placed tiles in reading order.
+#[derive(Clone)]
+pub struct Arrangement {
+    pub width: usize,
+    pub height: usize,
+    pub tiles: Vec<Tile>,
+}
+
+impl Arrangement {
+    pub fn get(&self, x: usize, y: usize) -> &Tile {
+        &self.tiles[(y * self.width) + x]
+    }
+
+    /// Top left, top right, bottom left, bottom right.
+    pub fn corners(&self) -> [&Tile; 4] {
+        [
+            self.get(0, 0),
+            self.get(self.width - 1, 0),
+            self.get(0, self.height - 1),
+            self.get(self.width - 1, self.height - 1),
+        ]
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Side {
+    Top,
+    Bottom,
+    Left,
+    Right,
+}
+
+/// One side of a tile, as given, that more than one other tile could sit
+/// against.
+#[derive(Debug, PartialEq)]
+pub struct Ambiguity {
+    pub identity: usize,
+    pub side: Side,
+    pub candidates: Vec<usize>,
+}
+
+// Where each position in a `width` by `height` grid goes under every whole
+// image symmetry that keeps those dimensions: the first four are the
+// identity and the reflections, and the last four transpose as well.
+fn symmetries(width: usize, height: usize) -> Vec<Vec<usize>> {
+    let count = if width == height { 8 } else { 4 };
+
+    (0..count)
+        .map(|symmetry| {
+            (0..width * height)
+                .map(|idx| {
+                    let (mut x, mut y) = (idx % width, idx / width);
+                    if symmetry & 1 != 0 {
+                        x = width - 1 - x;
+                    }
+                    if symmetry & 2 != 0 {
+                        y = height - 1 - y;
+                    }
+                    if symmetry & 4 != 0 {
+                        std::mem::swap(&mut x, &mut y);
+                    }
+                    (y * width) + x
+                })
+                .collect()
+        })
+        .collect()
+}
+
+fn no_arrangement(width: usize, height: usize, deepest: usize) -> JigsawError {
+    JigsawError::NoArrangement(width, height, deepest % width, deepest / width)
+}
+
+/// Fits tiles together into a rectangle. Every orientation of every tile is
+/// indexed by its left and top edges, so finding the tiles that can go next
+/// to one already placed is a single lookup.
+pub struct Assembler {
+    edge_length: usize,
+    // every distinct orientation of each tile, in input order
+    orientations: Vec<Vec<Tile>>,
+    // edge signature -> (tile, orientation) pairs with that edge
+    lefts: HashMap<Vec<bool>, Vec<(usize, usize)>>,
+    tops: HashMap<Vec<bool>, Vec<(usize, usize)>>,
+}
+
+impl Assembler {
+    pub fn new(tiles: Vec<Tile>) -> Result<Assembler, JigsawError> {
+        let edge_length = match tiles.first() {
+            None => return Err(JigsawError::NoTiles),
+            Some(tile) => tile.edge_length,
+        };
+        let mut orientations = Vec::with_capacity(tiles.len());
+        let mut lefts: HashMap<Vec<bool>, Vec<(usize, usize)>> = HashMap::new();
+        let mut tops: HashMap<Vec<bool>, Vec<(usize, usize)>> = HashMap::new();
+
+        for (idx, tile) in tiles.into_iter().enumerate() {
+            if tile.edge_length != edge_length {
+                return Err(JigsawError::MismatchedTile(
+                    tile.identity,
+                    tile.edge_length,
+                    edge_length,
+                ));
+            }
+
+            let variants = tile.variants();
+            for (variant_idx, variant) in variants.iter().enumerate() {
+                lefts
+                    .entry(variant.left.clone())
+                    .or_default()
+                    .push((idx, variant_idx));
+                tops.entry(variant.top.clone())
+                    .or_default()
+                    .push((idx, variant_idx));
+            }
+            orientations.push(variants);
+        }
+
+        Ok(Assembler {
+            edge_length,
+            orientations,
+            lefts,
+            tops,
+        })
+    }
+
+    pub fn tile_count(&self) -> usize {
+        self.orientations.len()
+    }
+
+    pub fn edge_length(&self) -> usize {
+        self.edge_length
+    }
+
+    /// Every width and height the tiles could fill, most square first. Only
+    /// one of each transposed pair is listed, since rotating a solution
+    /// gives the other.
+    pub fn dimensions(&self) -> Vec<(usize, usize)> {
+        let count = self.tile_count();
+        let mut result: Vec<(usize, usize)> = (1..=count)
+            .filter(|width| count.is_multiple_of(*width) && width * width >= count)
+            .map(|width| (width, count / width))
+            .collect();
+        result.sort_by_key(|(width, height)| width - height);
+        result
+    }
+
+    fn check_dimensions(&self, width: usize, height: usize) -> Result<(), JigsawError> {
+        if width * height != self.tile_count() {
+            return Err(JigsawError::WrongTileCount(
+                self.tile_count(),
+                width,
+                height,
+            ));
+        }
+        Ok(())
+    }
+
+    fn arrangement(&self, width: usize, height: usize, placed: &[(usize, usize)]) -> Arrangement {
+        Arrangement {
+            width,
+            height,
+            tiles: placed
+                .iter()
+                .map(|(tile, variant)| self.orientations[*tile][*variant].clone())
+                .collect(),
+        }
+    }
+
+    /// Finds an arrangement with the given dimensions. If there isn't one,
+    /// the error names the furthest position, in reading order, that the
+    /// search couldn't fill.
+    pub fn solve(&self, width: usize, height: usize) -> Result<Arrangement, JigsawError> {
+        self.check_dimensions(width, height)?;
+
+        let mut result = None;
+        let deepest = self.search(width, |placed| {
+            result = Some(self.arrangement(width, height, placed));
+            true
+        });
+
+        result.ok_or_else(|| no_arrangement(width, height, deepest))
+    }
+
+    /// Up to `limit` arrangements with the given dimensions, counting
+    /// arrangements that are rotations or reflections of each other (with
+    /// the same tile, turned the same way, in the same place) as one. If
+    /// there aren't any, the error is the one `solve` would give.
+    pub fn solutions(
+        &self,
+        width: usize,
+        height: usize,
+        limit: usize,
+    ) -> Result<Vec<Arrangement>, JigsawError> {
+        self.check_dimensions(width, height)?;
+
+        let pixels = symmetries(self.edge_length, self.edge_length);
+        let symmetries = symmetries(width, height);
+        // turned[tile][variant][symmetry] is the orientation a placed tile
+        // ends up in when the whole arrangement is turned by that symmetry
+        let turned: Vec<Vec<Vec<usize>>> = self
+            .orientations
+            .iter()
+            .map(|variants| {
+                variants
+                    .iter()
+                    .map(|variant| {
+                        pixels[..symmetries.len()]
+                            .iter()
+                            .map(|pixel_map| {
+                                let mut raw_data = vec![false; variant.raw_data.len()];
+                                for (idx, pixel) in variant.raw_data.iter().enumerate() {
+                                    raw_data[pixel_map[idx]] = *pixel;
+                                }
+                                variants
+                                    .iter()
+                                    .position(|x| x.raw_data == raw_data)
+                                    .expect("a tile's variants include every orientation")
+                            })
+                            .collect()
+                    })
+                    .collect()
+            })
+            .collect();
+        let mut seen = HashSet::new();
+        let mut result = Vec::new();
+
+        let deepest = self.search(width, |placed| {
+            let canonical = symmetries
+                .iter()
+                .enumerate()
+                .map(|(idx, symmetry)| {
+                    let mut layout = vec![(0, 0); placed.len()];
+                    for (position, (tile, variant)) in placed.iter().enumerate() {
+                        layout[symmetry[position]] = (*tile, turned[*tile][*variant][idx]);
+                    }
+                    layout
+                })
+                .min();
+
+            if seen.insert(canonical) {
+                result.push(self.arrangement(width, height, placed));
+            }
+            result.len() >= limit
+        });
+
+        if result.is_empty() {
+            return Err(no_arrangement(width, height, deepest));
+        }
+        Ok(result)
+    }
+
+    /// Finds an arrangement of any dimensions, trying the squarest first.
+    pub fn solve_any(&self) -> Result<Arrangement, JigsawError> {
+        let mut last_error = JigsawError::NoTiles;
+
+        for (width, height) in self.dimensions() {
+            match self.solve(width, height) {
+                Ok(arrangement) => return Ok(arrangement),
+                Err(e) => last_error = e,
+            }
+        }
+
+        Err(last_error)
+    }
+
+    fn candidates(&self, width: usize, placed: &[(usize, usize)]) -> Vec<(usize, usize)> {
+        let position = placed.len();
+        let x = position % width;
+        let neighbour = |offset: usize| {
+            let (tile, variant) = placed[position - offset];
+            &self.orientations[tile][variant]
+        };
+
+        if x > 0 {
+            let left = neighbour(1);
+            let matches = self.lefts.get(&left.right).cloned().unwrap_or_default();
+            if position >= width {
+                let above = neighbour(width);
+                matches
+                    .into_iter()
+                    .filter(|(tile, variant)| {
+                        self.orientations[*tile][*variant].top == above.bottom
+                    })
+                    .collect()
+            } else {
+                matches
+            }
+        } else if position >= width {
+            self.tops
+                .get(&neighbour(width).bottom)
+                .cloned()
+                .unwrap_or_default()
+        } else {
+            self.orientations
+                .iter()
+                .enumerate()
+                .flat_map(|(tile, variants)| (0..variants.len()).map(move |v| (tile, v)))
+                .collect()
+        }
+    }
+
+    /// Tiles with a side, as given, that matches more than one other tile.
+    pub fn ambiguities(&self) -> Vec<Ambiguity> {
+        let mut result = Vec::new();
+
+        for (idx, variants) in self.orientations.iter().enumerate() {
+            let tile = &variants[0];
+            // some orientation of a neighbour has a left (or top) edge equal
+            // to each of ours, once it's turned to face us
+            let sides = [
+                (Side::Top, self.tops.get(&tile.top)),
+                (Side::Bottom, self.tops.get(&tile.bottom)),
+                (Side::Left, self.lefts.get(&tile.left)),
+                (Side::Right, self.lefts.get(&tile.right)),
+            ];
+
+            for (side, matches) in sides.iter() {
+                let mut candidates: Vec<usize> = matches
+                    .iter()
+                    .flat_map(|x| x.iter())
+                    .filter(|(other, _)| *other != idx)
+                    .map(|(other, _)| self.orientations[*other][0].identity)
+                    .collect();
+                candidates.sort_unstable();
+                candidates.dedup();
+
+                if candidates.len() > 1 {
+                    result.push(Ambiguity {
+                        identity: tile.identity,
+                        side: *side,
+                        candidates,
+                    });
+                }
+            }
+        }
+
+        result
+    }
+
+    /// Calls `found` with every complete placement until it returns true,
+    /// and returns the furthest position reached.
+    fn search<F: FnMut(&[(usize, usize)]) -> bool>(&self, width: usize, mut found: F) -> usize {
+        let mut used = vec![false; self.tile_count()];
+        let mut placed = Vec::with_capacity(self.tile_count());
+        let mut deepest = 0;
+
+        self.place(width, &mut used, &mut placed, &mut deepest, &mut found);
+        deepest
+    }
+
+    fn place<F: FnMut(&[(usize, usize)]) -> bool>(
+        &self,
+        width: usize,
+        used: &mut Vec<bool>,
+        placed: &mut Vec<(usize, usize)>,
+        deepest: &mut usize,
+        found: &mut F,
+    ) -> bool {
+        *deepest = (*deepest).max(placed.len());
+        if placed.len() == used.len() {
+            return found(placed);
+        }
+
+        for (tile, variant) in self.candidates(width, placed) {
+            if used[tile] {
+                continue;
+            }
+
+            used[tile] = true;
+            placed.push((tile, variant));
+            if self.place(width, used, placed, deepest, found) {
+                return true;
+            }
+            placed.pop();
+            used[tile] = false;
+        }
+
+        false
+    }
+}
+
+#[cfg(test)]
+fn read_tiles(filename: &str) -> Vec<Tile> {
+    let contents = std::fs::read_to_string(filename).unwrap();
+    let mut lines = contents.lines();
+    let mut tiles = Vec::new();
+
+    while let Some(tile) = Tile::read(&mut lines).unwrap() {
+        tiles.push(tile);
+    }
+
+    tiles
+}
+
+#[test]
+fn day20_assembly() {
+    let assembler = Assembler::new(read_tiles("inputs/day20_test.txt")).unwrap();
+    assert_eq!(vec![(3, 3), (9, 1)], assembler.dimensions());
+
+    let arrangement = assembler.solve_any().unwrap();
+    let mut corners: Vec<usize> = arrangement
+        .corners()
+        .iter()
+        .map(|tile| tile.identity)
+        .collect();
+    corners.sort_unstable();
+    assert_eq!(vec![1171, 1951, 2971, 3079], corners);
+    assert_eq!(20899048083289, corners.iter().product::<usize>());
+    assert!(matches!(
+        assembler.solve(2, 4),
+        Err(JigsawError::WrongTileCount(9, 2, 4))
+    ));
+    assert!(matches!(
+        assembler.solve(9, 1),
+        Err(JigsawError::NoArrangement(9, 1, _, 0))
+    ));
+    assert_eq!(1, assembler.solutions(3, 3, 10).unwrap().len());
+    assert!(assembler.ambiguities().is_empty());
+}
+
+#[test]
+fn long_edges_and_strips() {
+    // three 20-pixel tiles that only fit together as 1 2 3. Each edge gets
+    // its own code (two pixels, placed so that no code reads the same as any
+    // other backwards), except where 1 meets 2 and 2 meets 3.
+    let code = |k: usize| [k + 1, 18];
+    let mut tiles = Vec::new();
+    for identity in 1..=3 {
+        let (left, right) = match identity {
+            1 => (3, 1),
+            2 => (1, 2),
+            _ => (2, 4),
+        };
+        let mut raw_data = vec![false; 400];
+        for i in code(left).iter() {
+            raw_data[i * 20] = true;
+        }
+        for i in code(right).iter() {
+            raw_data[i * 20 + 19] = true;
+        }
+        for i in code(4 + identity).iter() {
+            raw_data[*i] = true;
+        }
+        for i in code(7 + identity).iter() {
+            raw_data[380 + i] = true;
+        }
+        tiles.push(Tile::new(identity, vec![], 20, raw_data));
+    }
+
+    let assembler = Assembler::new(tiles.clone()).unwrap();
+    assert_eq!(vec![(3, 1)], assembler.dimensions());
+    let arrangement = assembler.solve(3, 1).unwrap();
+    let order: Vec<usize> = arrangement.tiles.iter().map(|x| x.identity).collect();
+    assert!(order == vec![1, 2, 3] || order == vec![3, 2, 1]);
+
+    let ends = Assembler::new(vec![tiles[0].clone(), tiles[2].clone()]).unwrap();
+    assert_eq!(
+        Some(JigsawError::NoArrangement(2, 1, 1, 0)),
+        ends.solve(2, 1).err()
+    );
+    assert_eq!(
+        Some(JigsawError::NoArrangement(2, 1, 1, 0)),
+        ends.solutions(2, 1, 2).err()
+    );
+
+    tiles.push(Tile::new(4, vec![], 3, vec![false; 9]));
+    assert!(matches!(
+        Assembler::new(tiles),
+        Err(JigsawError::MismatchedTile(4, 3, 20))
+    ));
+}
+
+#[test]
+fn ambiguous_tiles() {
+    let blanks = (1..=4)
+        .map(|identity| Tile::new(identity, vec![], 3, vec![false; 9]))
+        .collect();
+    let assembler = Assembler::new(blanks).unwrap();
+
+    // 4! placements, in groups of 8 that are symmetries of each other
+    assert_eq!(3, assembler.solutions(2, 2, 10).unwrap().len());
+    assert_eq!(2, assembler.solutions(2, 2, 2).unwrap().len());
+
+    // blank edges, but a mark inside that shows which way each tile is
+    // turned: 2 orders, times 4 orientations each, over 4 symmetries
+    let mut marked = vec![false; 16];
+    marked[5] = true;
+    let turned = (1..=2)
+        .map(|identity| Tile::new(identity, vec![], 4, marked.clone()))
+        .collect();
+    assert_eq!(
+        8,
+        Assembler::new(turned)
+            .unwrap()
+            .solutions(2, 1, 100)
+            .unwrap()
+            .len()
+    );
+
+    let ambiguities = assembler.ambiguities();
+    assert_eq!(16, ambiguities.len());
+    assert_eq!(
+        Some(&Ambiguity {
+            identity: 2,
+            side: Side::Left,
+            candidates: vec![1, 3, 4],
+        }),
+        ambiguities
+            .iter()
+            .find(|x| x.identity == 2 && x.side == Side::Left)
+    );
+}
@@ -0,0 +1,294 @@
+use crate::errors::JigsawError;
+use crate::jigsaw::Tile;
+use crate::random::Random;
+use std::collections::HashSet;
+
+// How many times to re-roll one edge before deciding the tiles are too small
+// to give every edge its own pattern.
+const EDGE_ATTEMPTS: usize = 1000;
+
+/// Reads a bitmap in the same `#`/`.` format as tiles.
+pub fn read_image(s: &str) -> Result<Vec<Vec<bool>>, JigsawError> {
+    let mut result: Vec<Vec<bool>> = Vec::new();
+
+    for line in s.lines().filter(|x| !x.is_empty()) {
+        let mut row = Vec::with_capacity(line.len());
+        for char in line.chars() {
+            match char {
+                '.' => row.push(false),
+                '#' => row.push(true),
+                _ => return Err(JigsawError::BadPixel(char)),
+            }
+        }
+        if !result.is_empty() && row.len() != result[0].len() {
+            return Err(JigsawError::RaggedImage(result.len() + 1));
+        }
+        result.push(row);
+    }
+
+    Ok(result)
+}
+
+/// Writes tiles back out in the format `Tile::read` accepts.
+pub fn render(tiles: &[Tile]) -> String {
+    let mut result = String::new();
+
+    for tile in tiles.iter() {
+        result.push_str(&format!("Tile {}:\n", tile.identity));
+        for y in 0..tile.edge_length {
+            for x in 0..tile.edge_length {
+                result.push(if tile.get(x, y) { '#' } else { '.' });
+            }
+            result.push('\n');
+        }
+        result.push('\n');
+    }
+
+    result
+}
+
+/// Cuts images into shuffled, scrambled tiles. Neighbouring tiles share
+/// their border, so a `edge_length` tile carries `edge_length - 2` pixels of
+/// the image in each direction, and the borders themselves are random.
+pub struct Generator {
+    edge_length: usize,
+    unique_edges: bool,
+    random: Random,
+}
+
+impl Generator {
+    pub fn new(seed: u64, edge_length: usize) -> Result<Generator, JigsawError> {
+        if edge_length < 3 {
+            return Err(JigsawError::BadTileSize(edge_length));
+        }
+
+        Ok(Generator {
+            edge_length,
+            unique_edges: false,
+            random: Random::new(seed),
+        })
+    }
+
+    /// Re-roll borders until every edge matches only the one it's supposed
+    /// to, and none of them read the same backwards, so the puzzle has a
+    /// single solution.
+    pub fn unique_edges(mut self, unique_edges: bool) -> Generator {
+        self.unique_edges = unique_edges;
+        self
+    }
+
+    /// A random image exactly big enough for `width` by `height` tiles.
+    pub fn random_image(&mut self, width: usize, height: usize) -> Vec<Vec<bool>> {
+        let chunk = self.edge_length - 2;
+
+        (0..height * chunk)
+            .map(|_| (0..width * chunk).map(|_| self.random.bit()).collect())
+            .collect()
+    }
+
+    pub fn generate(&mut self, width: usize, height: usize) -> Result<Vec<Tile>, JigsawError> {
+        let image = self.random_image(width, height);
+        self.cut(&image)
+    }
+
+    pub fn cut(&mut self, image: &[Vec<bool>]) -> Result<Vec<Tile>, JigsawError> {
+        let chunk = self.edge_length - 2;
+        let image_height = image.len();
+        let image_width = image.first().map(|x| x.len()).unwrap_or(0);
+
+        if image_width == 0
+            || !image_width.is_multiple_of(chunk)
+            || !image_height.is_multiple_of(chunk)
+        {
+            return Err(JigsawError::ImageSize(image_width, image_height, chunk));
+        }
+
+        // Lay the tiles out on one big grid where neighbours overlap by a
+        // row or column; that overlap is their shared border.
+        let step = self.edge_length - 1;
+        let width = image_width / chunk;
+        let height = image_height / chunk;
+        let grid_width = (width * step) + 1;
+        let mut grid = vec![false; grid_width * ((height * step) + 1)];
+
+        for (y, row) in image.iter().enumerate() {
+            for (x, pixel) in row.iter().enumerate() {
+                let grid_x = ((x / chunk) * step) + (x % chunk) + 1;
+                let grid_y = ((y / chunk) * step) + (y % chunk) + 1;
+                grid[(grid_y * grid_width) + grid_x] = *pixel;
+            }
+        }
+
+        for y in 0..=height {
+            for x in 0..=width {
+                grid[(y * step * grid_width) + (x * step)] = self.random.bit();
+            }
+        }
+
+        // every border segment, as the grid indices of its pixels
+        let mut segments: Vec<Vec<usize>> = Vec::new();
+        for y in 0..height {
+            for x in 0..=width {
+                let start = (y * step * grid_width) + (x * step);
+                segments.push(
+                    (0..self.edge_length)
+                        .map(|k| start + (k * grid_width))
+                        .collect(),
+                );
+            }
+        }
+        for y in 0..=height {
+            for x in 0..width {
+                let start = (y * step * grid_width) + (x * step);
+                segments.push((0..self.edge_length).map(|k| start + k).collect());
+            }
+        }
+
+        let mut seen = HashSet::new();
+        for segment in segments.iter() {
+            self.fill_segment(&mut grid, segment, &mut seen)?;
+        }
+
+        let mut identities = HashSet::new();
+        let identity_range = 9000.max(width * height * 10);
+        let mut tiles = Vec::with_capacity(width * height);
+
+        for y in 0..height {
+            for x in 0..width {
+                let mut raw_data = Vec::with_capacity(self.edge_length * self.edge_length);
+                for tile_y in 0..self.edge_length {
+                    let start = ((y * step + tile_y) * grid_width) + (x * step);
+                    raw_data.extend_from_slice(&grid[start..start + self.edge_length]);
+                }
+
+                let mut identity = 1000 + self.random.below(identity_range);
+                while !identities.insert(identity) {
+                    identity = 1000 + self.random.below(identity_range);
+                }
+
+                let tile = Tile::new(identity, vec![], self.edge_length, raw_data);
+                tiles.push(self.scramble(tile));
+            }
+        }
+
+        self.random.shuffle(&mut tiles);
+
+        Ok(tiles)
+    }
+
+    fn fill_segment(
+        &mut self,
+        grid: &mut [bool],
+        segment: &[usize],
+        seen: &mut HashSet<Vec<bool>>,
+    ) -> Result<(), JigsawError> {
+        let inner = &segment[1..segment.len() - 1];
+
+        for _ in 0..EDGE_ATTEMPTS {
+            for idx in inner.iter() {
+                grid[*idx] = self.random.bit();
+            }
+
+            if !self.unique_edges {
+                return Ok(());
+            }
+
+            let edge: Vec<bool> = segment.iter().map(|idx| grid[*idx]).collect();
+            let reversed: Vec<bool> = edge.iter().rev().cloned().collect();
+            if edge != reversed && !seen.contains(&edge) && !seen.contains(&reversed) {
+                seen.insert(edge);
+                return Ok(());
+            }
+        }
+
+        Err(JigsawError::TooFewEdges(self.edge_length))
+    }
+
+    fn scramble(&mut self, mut tile: Tile) -> Tile {
+        for _ in 0..self.random.below(4) {
+            tile = tile.rotate();
+        }
+        if self.random.bit() {
+            tile = tile.flip_over_y();
+        }
+        tile
+    }
+}
+
+#[cfg(test)]
+fn interior(arrangement: &crate::jigsaw::Arrangement) -> Vec<Vec<bool>> {
+    let chunk = arrangement.tiles[0].edge_length - 2;
+
+    (0..arrangement.height * chunk)
+        .map(|y| {
+            (0..arrangement.width * chunk)
+                .map(|x| {
+                    arrangement
+                        .get(x / chunk, y / chunk)
+                        .get((x % chunk) + 1, (y % chunk) + 1)
+                })
+                .collect()
+        })
+        .collect()
+}
+
+#[test]
+fn generated_puzzles_reassemble() {
+    use crate::jigsaw::Assembler;
+
+    let mut generator = Generator::new(2020, 10).unwrap().unique_edges(true);
+    let image = generator.random_image(4, 3);
+    let tiles = generator.cut(&image).unwrap();
+    assert_eq!(12, tiles.len());
+
+    let text = render(&tiles);
+    let mut lines = text.lines();
+    let mut read_back = Vec::new();
+    while let Some(tile) = Tile::read(&mut lines).unwrap() {
+        read_back.push(tile);
+    }
+    assert!(tiles == read_back);
+
+    let assembler = Assembler::new(read_back).unwrap();
+    assert!(assembler.ambiguities().is_empty());
+    let solutions = assembler.solutions(4, 3, 2).unwrap();
+    assert_eq!(1, solutions.len());
+
+    // the image comes back, up to the same whole-image flips
+    let rebuilt = interior(&solutions[0]);
+    let flips: Vec<Vec<Vec<bool>>> = (0..4)
+        .map(|flip| {
+            let mut result = image.clone();
+            if flip & 1 != 0 {
+                result.iter_mut().for_each(|row| row.reverse());
+            }
+            if flip & 2 != 0 {
+                result.reverse();
+            }
+            result
+        })
+        .collect();
+    assert!(flips.contains(&rebuilt));
+}
+
+#[test]
+fn generator_errors() {
+    assert_eq!(
+        Some(JigsawError::BadTileSize(2)),
+        Generator::new(1, 2).err()
+    );
+
+    let mut generator = Generator::new(1, 4).unwrap();
+    assert_eq!(
+        Some(JigsawError::ImageSize(3, 2, 2)),
+        generator.cut(&[vec![true; 3], vec![false; 3]]).err()
+    );
+    assert_eq!(
+        Some(JigsawError::TooFewEdges(4)),
+        generator.unique_edges(true).generate(4, 4).err()
+    );
+
+    assert_eq!(Ok(vec![vec![true, false]]), read_image("#.\n"));
+    assert_eq!(Err(JigsawError::RaggedImage(2)), read_image("#.\n#\n"));
+    assert_eq!(Err(JigsawError::BadPixel('x')), read_image("x\n"));
+}
@@ -1,5 +1,8 @@
 extern crate lalrpop_util;
 
 pub mod errors;
+pub mod jigsaw;
+pub mod machine;
 pub mod map;
 pub mod math;
+pub mod random;
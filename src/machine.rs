@@ -0,0 +1,286 @@
+mod abstract_interpreter;
+mod analysis;
+mod assembler;
+mod debugger;
+mod instruction;
+mod opcodes;
+mod trace;
+
+pub use crate::machine::abstract_interpreter::{
+    AbstractOutcome, AbstractResult, AbstractState, Interval, Verdict,
+};
+pub use crate::machine::analysis::ControlFlow;
+pub use crate::machine::assembler::{assemble, disassemble};
+pub use crate::machine::debugger::{Comparison, DebugCommand, Debugger, StopReason, Watch};
+pub use crate::machine::instruction::{Instruction, Operand};
+pub use crate::machine::opcodes::{Flow, Opcode, OperandKind};
+pub use crate::machine::trace::{diverge, read_trace, Divergence, TraceEntry};
+
+use crate::errors::{ExecutionError, InstructionParseError};
+use std::collections::{BTreeMap, HashSet, VecDeque};
+use std::str::FromStr;
+
+pub const ACCUMULATOR: &str = "acc";
+
+#[derive(Clone)]
+pub struct Machine {
+    pub instructions: Vec<Instruction>,
+    pub registers: BTreeMap<String, isize>,
+    pub location: isize,
+    pub input: VecDeque<isize>,
+    pub output: Vec<isize>,
+}
+
+impl FromStr for Machine {
+    type Err = InstructionParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut instructions = Vec::new();
+
+        for line in s.lines() {
+            let instruction = Instruction::from_str(line)?;
+            instructions.push(instruction);
+        }
+
+        Ok(Machine::new(instructions))
+    }
+}
+
+impl Machine {
+    pub fn new(instructions: Vec<Instruction>) -> Machine {
+        Machine {
+            instructions,
+            registers: BTreeMap::new(),
+            location: 0,
+            input: VecDeque::new(),
+            output: Vec::new(),
+        }
+    }
+
+    pub fn accumulator(&self) -> isize {
+        self.register(ACCUMULATOR)
+    }
+
+    pub fn register(&self, name: &str) -> isize {
+        self.registers.get(name).copied().unwrap_or(0)
+    }
+
+    pub fn register_mut(&mut self, name: &str) -> &mut isize {
+        self.registers.entry(name.to_string()).or_insert(0)
+    }
+
+    pub fn value(&self, operand: &Operand) -> isize {
+        match operand {
+            Operand::Immediate(x) => *x,
+            Operand::Register(r) => self.register(r),
+        }
+    }
+
+    fn set(&mut self, operand: &Operand, value: isize) {
+        // Instruction::new makes sure written operands are registers.
+        if let Operand::Register(r) = operand {
+            *self.register_mut(r) = value;
+        }
+    }
+
+    pub fn halted(&self) -> bool {
+        self.location == (self.instructions.len() as isize)
+    }
+
+    pub fn pretty_print(&self) {
+        for (idx, instr) in self.instructions.iter().enumerate() {
+            let pointer = if (idx as isize) == self.location {
+                "--> "
+            } else {
+                "    "
+            };
+            println!("{} {:04}: {}", pointer, idx, instr);
+        }
+    }
+
+    pub fn step(&mut self) -> Result<(), ExecutionError> {
+        if self.location < 0 || self.location >= (self.instructions.len() as isize) {
+            return Err(ExecutionError::NonExistentLocation(self.location));
+        }
+
+        // The handlers need the whole machine, so lend them the operands
+        // for the length of the call rather than copying them every step.
+        let idx = self.location as usize;
+        let opcode = self.instructions[idx].opcode;
+        let operands = std::mem::take(&mut self.instructions[idx].operands);
+        let offset = opcode.execute(self, &operands);
+        self.instructions[idx].operands = operands;
+
+        self.location = self
+            .location
+            .checked_add(offset?)
+            .ok_or(ExecutionError::Overflow(self.location))?;
+        Ok(())
+    }
+
+    /// Runs the machine until it halts. Programs that loop forever will
+    /// make this loop forever, too.
+    pub fn run(&mut self) -> Result<(), ExecutionError> {
+        while !self.halted() {
+            self.step()?;
+        }
+        Ok(())
+    }
+
+    /// Runs the machine until it either halts, returning `(true, acc)`, or
+    /// is about to revisit an instruction, returning `(false, acc)` with the
+    /// accumulator as it was before the repeat. Revisiting an instruction
+    /// only means a loop for programs without conditional jumps.
+    pub fn terminates(&mut self) -> Result<(bool, isize), ExecutionError> {
+        self.run_observed(|_, _| Ok(()))
+    }
+
+    /// The loop behind `terminates`, calling `observe` with the location
+    /// of each instruction just after it runs.
+    pub(crate) fn run_observed<E, F>(&mut self, mut observe: F) -> Result<(bool, isize), E>
+    where
+        E: From<ExecutionError>,
+        F: FnMut(isize, &Machine) -> Result<(), E>,
+    {
+        let mut visited_locations = HashSet::new();
+        loop {
+            let current_location = self.location;
+            let current_accumulator = self.accumulator();
+
+            visited_locations.insert(current_location);
+            self.step()?;
+            observe(current_location, self)?;
+
+            if visited_locations.contains(&self.location) {
+                return Ok((false, current_accumulator));
+            }
+
+            if self.halted() {
+                return Ok((true, self.accumulator()));
+            }
+        }
+    }
+
+    /// Returns a copy of this machine with the instruction at `location`
+    /// flipped between `jmp` and `nop`, as used by
+    /// `ControlFlow::repair` and `VariantGenerator`.
+    pub fn flipped(&self, location: isize) -> Option<Machine> {
+        let current = self.instructions.get(location as usize)?;
+        let flipped = if current.is("jmp") {
+            current.with_opcode("nop")?
+        } else if current.is("nop") {
+            current.with_opcode("jmp")?
+        } else {
+            return None;
+        };
+
+        let mut retval = self.clone();
+        retval.instructions[location as usize] = flipped;
+        Some(retval)
+    }
+
+    pub fn variants(&self) -> VariantGenerator {
+        VariantGenerator {
+            next_offset: 0,
+            base_machine: self.clone(),
+        }
+    }
+}
+
+pub struct VariantGenerator {
+    next_offset: usize,
+    base_machine: Machine,
+}
+
+impl Iterator for VariantGenerator {
+    type Item = Machine;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if self.next_offset >= self.base_machine.instructions.len() {
+                return None;
+            }
+
+            let offset = self.next_offset as isize;
+            self.next_offset += 1;
+
+            if let Some(retval) = self.base_machine.flipped(offset) {
+                return Some(retval);
+            }
+        }
+    }
+}
+
+#[test]
+fn day8_example() {
+    let contents = std::fs::read_to_string("inputs/day8_test.txt").unwrap();
+    let machine = Machine::from_str(&contents).unwrap();
+    assert_eq!((false, 5), machine.clone().terminates().unwrap());
+    let halting: Vec<isize> = machine
+        .variants()
+        .filter_map(|mut x| match x.terminates() {
+            Ok((true, acc)) => Some(acc),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(vec![8], halting);
+}
+
+#[test]
+fn registers_and_io() {
+    let program = "in a\ncpy 1 b\nmul b a\nsub a 1\njgz a -2\nout b\nmod b 7\nout b\n";
+    let mut machine = Machine::from_str(program).unwrap();
+    machine.input.push_back(5);
+    machine.run().unwrap();
+    assert_eq!(vec![120, 1], machine.output);
+}
+
+#[test]
+fn arithmetic_faults() {
+    let run = |program: &str| Machine::from_str(program).unwrap().run();
+    let big = format!("cpy {} a\n", isize::MAX);
+    assert!(matches!(
+        run(&format!("{}add a 1\n", big)),
+        Err(ExecutionError::Overflow(1))
+    ));
+    assert!(matches!(
+        run(&format!("{}mul a 2\n", big)),
+        Err(ExecutionError::Overflow(1))
+    ));
+    assert!(matches!(
+        run(&format!("cpy {} a\nsub a 1\n", isize::MIN)),
+        Err(ExecutionError::Overflow(1))
+    ));
+    assert!(matches!(
+        run(&format!("acc {}\nacc 1\n", isize::MAX)),
+        Err(ExecutionError::Overflow(1))
+    ));
+    assert!(matches!(
+        run(&format!("nop 0\njmp {}\n", isize::MAX)),
+        Err(ExecutionError::Overflow(1))
+    ));
+    assert!(matches!(
+        run("cpy 5 a\nmod a 0\n"),
+        Err(ExecutionError::DivisionByZero(1))
+    ));
+    assert!(matches!(
+        run(&format!("cpy {} a\nmod a -1\n", isize::MIN)),
+        Err(ExecutionError::Overflow(1))
+    ));
+}
+
+#[test]
+fn bad_instructions() {
+    assert!(matches!(
+        Instruction::from_str("cpy a 1"),
+        Err(InstructionParseError::ExpectedRegister(_, _))
+    ));
+    assert!(matches!(
+        Instruction::from_str("jnz a"),
+        Err(InstructionParseError::WrongOperandCount(_, 2, 1))
+    ));
+    assert!(matches!(
+        Instruction::from_str("frob +3"),
+        Err(InstructionParseError::UnknownOpcode(_))
+    ));
+}
@@ -0,0 +1,550 @@
+use crate::machine::{Flow, Machine, Operand, ACCUMULATOR};
+use petgraph::algo::tarjan_scc;
+use petgraph::graphmap::DiGraphMap;
+use std::cmp::{max, min};
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::fmt;
+
+/// A range of possible values; a missing bound is unbounded.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Interval {
+    pub low: Option<isize>,
+    pub high: Option<isize>,
+}
+
+impl Interval {
+    pub fn exactly(x: isize) -> Interval {
+        Interval {
+            low: Some(x),
+            high: Some(x),
+        }
+    }
+
+    pub fn between(low: isize, high: isize) -> Interval {
+        Interval {
+            low: Some(low),
+            high: Some(high),
+        }
+    }
+
+    pub fn anything() -> Interval {
+        Interval {
+            low: None,
+            high: None,
+        }
+    }
+
+    pub fn contains(&self, x: isize) -> bool {
+        self.low.is_none_or(|l| l <= x) && self.high.is_none_or(|h| x <= h)
+    }
+
+    pub fn may_be_nonzero(&self) -> bool {
+        *self != Interval::exactly(0)
+    }
+
+    pub fn may_be_positive(&self) -> bool {
+        self.high.is_none_or(|h| h > 0)
+    }
+
+    pub fn may_be_nonpositive(&self) -> bool {
+        self.low.is_none_or(|l| l <= 0)
+    }
+
+    pub fn is_bounded(&self) -> bool {
+        self.low.is_some() && self.high.is_some()
+    }
+
+    pub fn join(&self, other: &Interval) -> Interval {
+        Interval {
+            low: self.low.and_then(|a| other.low.map(|b| min(a, b))),
+            high: self.high.and_then(|a| other.high.map(|b| max(a, b))),
+        }
+    }
+
+    /// Like `join`, but any bound that moved is dropped entirely, so that
+    /// loops reach a fixed point.
+    pub fn widen(&self, other: &Interval) -> Interval {
+        let joined = self.join(other);
+        Interval {
+            low: if joined.low == self.low {
+                self.low
+            } else {
+                None
+            },
+            high: if joined.high == self.high {
+                self.high
+            } else {
+                None
+            },
+        }
+    }
+
+    pub fn add(&self, other: &Interval) -> Interval {
+        Interval {
+            low: self
+                .low
+                .and_then(|a| other.low.and_then(|b| a.checked_add(b))),
+            high: self
+                .high
+                .and_then(|a| other.high.and_then(|b| a.checked_add(b))),
+        }
+    }
+
+    pub fn negate(&self) -> Interval {
+        Interval {
+            low: self.high.and_then(|h| h.checked_neg()),
+            high: self.low.and_then(|l| l.checked_neg()),
+        }
+    }
+
+    pub fn sub(&self, other: &Interval) -> Interval {
+        self.add(&other.negate())
+    }
+
+    pub fn mul(&self, other: &Interval) -> Interval {
+        if *self == Interval::exactly(0) || *other == Interval::exactly(0) {
+            return Interval::exactly(0);
+        }
+
+        match (self.low, self.high, other.low, other.high) {
+            (Some(a), Some(b), Some(c), Some(d)) => {
+                let products = [
+                    a.checked_mul(c),
+                    a.checked_mul(d),
+                    b.checked_mul(c),
+                    b.checked_mul(d),
+                ];
+                if products.iter().any(|x| x.is_none()) {
+                    return Interval::anything();
+                }
+                let products: Vec<isize> = products.iter().flatten().copied().collect();
+                Interval::between(
+                    *products.iter().min().unwrap(),
+                    *products.iter().max().unwrap(),
+                )
+            }
+            _ => Interval::anything(),
+        }
+    }
+
+    /// The result of `rem_euclid`, ignoring the case where the divisor is
+    /// zero; callers check that with `contains(0)`.
+    pub fn rem_euclid(&self, other: &Interval) -> Interval {
+        if let (Some(a), Some(b), Some(c), Some(d)) = (self.low, self.high, other.low, other.high) {
+            if a == b && c == d && c != 0 {
+                return Interval::exactly(a.rem_euclid(c));
+            }
+        }
+
+        let largest = match (other.low, other.high) {
+            (Some(l), Some(h)) => l
+                .checked_abs()
+                .and_then(|l| h.checked_abs().map(|h| max(l, h))),
+            _ => None,
+        };
+
+        Interval {
+            low: Some(0),
+            high: largest.map(|x| max(x - 1, 0)),
+        }
+    }
+}
+
+impl fmt::Display for Interval {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (self.low, self.high) {
+            (Some(l), Some(h)) if l == h => write!(f, "{}", l),
+            (Some(l), Some(h)) => write!(f, "[{}, {}]", l, h),
+            (Some(l), None) => write!(f, "[{}, +inf)", l),
+            (None, Some(h)) => write!(f, "(-inf, {}]", h),
+            (None, None) => write!(f, "(-inf, +inf)"),
+        }
+    }
+}
+
+/// The possible values of every register at one point in the program.
+/// Registers that aren't mentioned have the `unset` value. `input` is what
+/// `in` might read, and `input_left` how many values there might be left
+/// for it to read.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AbstractState {
+    pub registers: BTreeMap<String, Interval>,
+    pub unset: Interval,
+    pub input: Interval,
+    pub input_left: Interval,
+}
+
+impl AbstractState {
+    pub fn register(&self, name: &str) -> Interval {
+        self.registers.get(name).copied().unwrap_or(self.unset)
+    }
+
+    pub fn value(&self, operand: &Operand) -> Interval {
+        match operand {
+            Operand::Immediate(x) => Interval::exactly(*x),
+            Operand::Register(r) => self.register(r),
+        }
+    }
+
+    pub fn set(&mut self, operand: &Operand, value: Interval) {
+        if let Operand::Register(r) = operand {
+            self.registers.insert(r.clone(), value);
+        }
+    }
+
+    fn combine(
+        &self,
+        other: &AbstractState,
+        f: fn(&Interval, &Interval) -> Interval,
+    ) -> AbstractState {
+        let names: BTreeSet<&String> = self
+            .registers
+            .keys()
+            .chain(other.registers.keys())
+            .collect();
+        let mut registers = BTreeMap::new();
+
+        for name in names {
+            registers.insert(name.clone(), f(&self.register(name), &other.register(name)));
+        }
+
+        AbstractState {
+            registers,
+            unset: f(&self.unset, &other.unset),
+            input: f(&self.input, &other.input),
+            input_left: f(&self.input_left, &other.input_left),
+        }
+    }
+}
+
+/// What an instruction might do to control flow, as computed by an opcode's
+/// abstract handler.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AbstractOutcome {
+    pub falls_through: bool,
+    pub jumps: bool,
+    pub may_fault: bool,
+}
+
+impl AbstractOutcome {
+    pub fn next() -> AbstractOutcome {
+        AbstractOutcome {
+            falls_through: true,
+            jumps: false,
+            may_fault: false,
+        }
+    }
+
+    pub fn jump() -> AbstractOutcome {
+        AbstractOutcome {
+            falls_through: false,
+            jumps: true,
+            may_fault: false,
+        }
+    }
+
+    pub fn branch(jumps: bool, falls_through: bool) -> AbstractOutcome {
+        AbstractOutcome {
+            falls_through,
+            jumps,
+            may_fault: false,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Verdict {
+    Halts,
+    Loops,
+    Faults,
+    /// More than one of the above might happen, depending on the start
+    /// state or on precision the analysis doesn't have.
+    Unknown,
+}
+
+pub struct AbstractResult {
+    /// The state on entry to each instruction, or `None` if it's never run.
+    pub states: Vec<Option<AbstractState>>,
+    /// The state when the program halts, if it can.
+    pub exit: Option<AbstractState>,
+    pub may_halt: bool,
+    pub may_loop: bool,
+    /// Instructions that might fault, either by jumping somewhere that
+    /// isn't an instruction or by failing themselves (e.g. `mod` by zero,
+    /// overflow, or `in` with nothing left to read).
+    pub faults: BTreeSet<isize>,
+}
+
+impl AbstractResult {
+    pub fn verdict(&self) -> Verdict {
+        match (self.may_halt, self.may_loop, !self.faults.is_empty()) {
+            (true, false, false) => Verdict::Halts,
+            (false, true, false) => Verdict::Loops,
+            (false, false, true) => Verdict::Faults,
+            _ => Verdict::Unknown,
+        }
+    }
+
+    pub fn accumulator_at(&self, location: isize) -> Option<Interval> {
+        let state = self.states.get(location as usize)?.as_ref()?;
+        Some(state.register(ACCUMULATOR))
+    }
+
+    /// Every value the accumulator can hold, anywhere in the run.
+    pub fn accumulator_range(&self) -> Option<Interval> {
+        self.states
+            .iter()
+            .chain(std::iter::once(&self.exit))
+            .flatten()
+            .map(|x| x.register(ACCUMULATOR))
+            .fold(None, |acc, x| match acc {
+                None => Some(x),
+                Some(y) => Some(x.join(&y)),
+            })
+    }
+}
+
+// How many times we'll join into an instruction's state before we start
+// widening instead.
+const WIDEN_AFTER: usize = 3;
+
+impl Machine {
+    /// The abstract state matching this machine's actual starting state.
+    pub fn initial_state(&self) -> AbstractState {
+        AbstractState {
+            registers: self
+                .registers
+                .iter()
+                .map(|(k, v)| (k.clone(), Interval::exactly(*v)))
+                .collect(),
+            unset: Interval::exactly(0),
+            input: Interval::anything(),
+            input_left: Interval::exactly(self.input.len() as isize),
+        }
+    }
+
+    /// The abstract state covering every possible starting state.
+    pub fn any_state(&self) -> AbstractState {
+        AbstractState {
+            registers: BTreeMap::new(),
+            unset: Interval::anything(),
+            input: Interval::anything(),
+            input_left: Interval::anything(),
+        }
+    }
+
+    /// Runs the program over intervals instead of numbers, starting from
+    /// `start` at the machine's current location, and says what might
+    /// happen. Widening forgets how many times a loop has gone round, so
+    /// even a straight-line loop like day 8's is only reported as
+    /// `may_loop`, with possible overflow faults and an `Unknown` verdict,
+    /// rather than as a definite loop; use `terminates` for that.
+    pub fn abstract_run(&self, start: AbstractState) -> AbstractResult {
+        let length = self.instructions.len() as isize;
+        let mut states: Vec<Option<AbstractState>> = vec![None; self.instructions.len()];
+        let mut visits = vec![0; self.instructions.len()];
+        let mut exit: Option<AbstractState> = None;
+        let mut faults = BTreeSet::new();
+        let mut edges = DiGraphMap::new();
+        let mut worklist = VecDeque::new();
+        let mut queued = vec![false; self.instructions.len()];
+
+        if self.location < 0 || self.location >= length {
+            return AbstractResult {
+                states,
+                may_halt: self.location == length,
+                exit: if self.location == length {
+                    Some(start)
+                } else {
+                    None
+                },
+                may_loop: false,
+                faults: if self.location == length {
+                    BTreeSet::new()
+                } else {
+                    vec![self.location].into_iter().collect()
+                },
+            };
+        }
+
+        states[self.location as usize] = Some(start);
+        worklist.push_back(self.location);
+        queued[self.location as usize] = true;
+
+        while let Some(location) = worklist.pop_front() {
+            queued[location as usize] = false;
+            let instruction = &self.instructions[location as usize];
+            let mut state = states[location as usize].clone().unwrap();
+            let outcome = instruction
+                .opcode
+                .transfer(&mut state, &instruction.operands);
+            let mut successors = Vec::new();
+
+            if outcome.may_fault {
+                faults.insert(location);
+            }
+            if outcome.falls_through {
+                successors.push(location + 1);
+            }
+            if outcome.jumps {
+                let offset = match instruction.opcode.flow {
+                    Flow::Jump(idx) | Flow::Branch(idx) => state.value(&instruction.operands[idx]),
+                    Flow::Next => Interval::exactly(1),
+                };
+                let targets = match (offset.low, offset.high) {
+                    (Some(l), Some(h)) if h.checked_sub(l).is_some_and(|x| x <= length) => (l..=h)
+                        .map(|x| location.checked_add(x))
+                        .collect::<Option<Vec<isize>>>(),
+                    _ => None,
+                };
+                match targets {
+                    Some(targets) => successors.extend(targets),
+                    None => {
+                        // we have no idea where this goes
+                        faults.insert(location);
+                        successors.extend(0..=length);
+                    }
+                }
+            }
+
+            for target in successors {
+                edges.add_edge(location, target, ());
+
+                if target == length {
+                    exit = Some(match exit {
+                        None => state.clone(),
+                        Some(previous) => previous.combine(&state, Interval::join),
+                    });
+                    continue;
+                }
+
+                if target < 0 || target > length {
+                    faults.insert(location);
+                    continue;
+                }
+
+                let idx = target as usize;
+                let updated = match &states[idx] {
+                    None => state.clone(),
+                    Some(previous) if visits[idx] < WIDEN_AFTER => {
+                        previous.combine(&state, Interval::join)
+                    }
+                    Some(previous) => previous.combine(&state, Interval::widen),
+                };
+
+                if states[idx].as_ref() != Some(&updated) {
+                    states[idx] = Some(updated);
+                    visits[idx] += 1;
+                    if !queued[idx] {
+                        queued[idx] = true;
+                        worklist.push_back(target);
+                    }
+                }
+            }
+        }
+
+        let may_loop = tarjan_scc(&edges)
+            .iter()
+            .any(|scc| scc.len() > 1 || edges.contains_edge(scc[0], scc[0]));
+
+        AbstractResult {
+            states,
+            may_halt: exit.is_some(),
+            exit,
+            may_loop,
+            faults,
+        }
+    }
+}
+
+#[test]
+fn interval_arithmetic() {
+    let a = Interval::between(-2, 3);
+    let b = Interval::between(4, 5);
+
+    assert_eq!(Interval::between(2, 8), a.add(&b));
+    assert_eq!(Interval::between(-7, -1), a.sub(&b));
+    assert_eq!(Interval::between(-10, 15), a.mul(&b));
+    assert_eq!(Interval::between(0, 4), a.rem_euclid(&b));
+    assert_eq!(Interval::between(-2, 5), a.join(&b));
+    assert_eq!(
+        Interval {
+            low: Some(-2),
+            high: None
+        },
+        a.widen(&b)
+    );
+    assert!(!b.contains(0));
+    assert_eq!("[-2, 3]", a.to_string());
+}
+
+#[test]
+fn day8_abstract() {
+    use std::str::FromStr;
+
+    let contents = std::fs::read_to_string("inputs/day8_test.txt").unwrap();
+    let machine = Machine::from_str(&contents).unwrap();
+    let looping = machine.abstract_run(machine.initial_state());
+
+    // left to itself the loop would add to acc until it overflowed, so
+    // it's not only a loop
+    assert_eq!(Verdict::Unknown, looping.verdict());
+    assert!(looping.may_loop && !looping.may_halt);
+    assert_eq!(
+        vec![1, 3, 6],
+        looping.faults.iter().copied().collect::<Vec<_>>()
+    );
+    assert_eq!(Some(Interval::exactly(0)), looping.accumulator_at(0));
+    assert_eq!(None, looping.accumulator_at(5));
+
+    let fixed = machine.flipped(7).unwrap();
+    let halting = fixed.abstract_run(fixed.initial_state());
+    assert_eq!(Verdict::Halts, halting.verdict());
+    assert_eq!(
+        Some(Interval::exactly(8)),
+        halting.exit.map(|x| x.register(ACCUMULATOR))
+    );
+
+    // starting from anything, the additions might overflow
+    let anywhere = fixed.abstract_run(fixed.any_state());
+    assert_eq!(Verdict::Unknown, anywhere.verdict());
+    assert!(anywhere.may_halt && !anywhere.may_loop);
+    assert_eq!(Some(Interval::anything()), anywhere.accumulator_range());
+}
+
+#[test]
+fn branches_and_faults() {
+    use std::str::FromStr;
+
+    // counts a down from whatever it starts as, if it's positive
+    let machine = Machine::from_str("jgz a 2\njmp +3\nsub a 1\njgz a -1\nout a\n").unwrap();
+    let result = machine.abstract_run(machine.any_state());
+    assert_eq!(Verdict::Unknown, result.verdict());
+    assert!(result.may_halt && result.may_loop);
+    assert_eq!(vec![2], result.faults.into_iter().collect::<Vec<_>>());
+
+    let start = machine.initial_state();
+    assert_eq!(Verdict::Halts, machine.abstract_run(start).verdict());
+
+    let faulting = Machine::from_str("in a\nmod a a\njmp -5\n").unwrap();
+    let result = faulting.abstract_run(faulting.initial_state());
+    assert_eq!(Verdict::Faults, result.verdict());
+    assert_eq!(vec![0, 1, 2], result.faults.into_iter().collect::<Vec<_>>());
+
+    // with its input known, `in` can't run dry
+    let mut reading = Machine::from_str("in a\nin b\nout b\n").unwrap();
+    reading.input.extend(vec![1, 2]);
+    let result = reading.abstract_run(reading.initial_state());
+    assert_eq!(Verdict::Halts, result.verdict());
+    reading.input.pop_back();
+    let result = reading.abstract_run(reading.initial_state());
+    assert_eq!(vec![1], result.faults.into_iter().collect::<Vec<_>>());
+
+    // huge jump offsets are faults, not panics
+    let far = Machine::from_str("in a\nmul a a\njnz 1 a\n").unwrap();
+    let result = far.abstract_run(far.any_state());
+    assert!(result.faults.contains(&2));
+    let far = format!("nop +1\njmp {}\n", isize::MAX);
+    let far = Machine::from_str(&far).unwrap();
+    assert!(far.abstract_run(far.initial_state()).faults.contains(&1));
+}
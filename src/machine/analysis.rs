@@ -0,0 +1,230 @@
+use crate::machine::{Flow, Instruction, Machine, Operand};
+use petgraph::algo::tarjan_scc;
+use petgraph::graphmap::DiGraphMap;
+use petgraph::visit::{Dfs, Reversed};
+use std::collections::BTreeSet;
+
+/// The control-flow graph of a program. Nodes are instruction locations;
+/// the location just past the last instruction is where the program halts,
+/// and any other out-of-range location is a fault.
+pub struct ControlFlow {
+    pub graph: DiGraphMap<isize, ()>,
+    pub length: isize,
+    /// Jumps whose offset lives in a register, so we can't know where they
+    /// go. Their taken edge is missing from `graph`.
+    pub dynamic_jumps: Vec<isize>,
+    /// Whether every instruction goes to exactly one place, so that each
+    /// path through `graph` is one the program really could take. Branches
+    /// and dynamic jumps make the graph an over-approximation.
+    pub exact: bool,
+}
+
+fn jump_target(location: isize, instruction: &Instruction, operand: usize) -> Option<isize> {
+    match &instruction.operands[operand] {
+        // a jump far enough to overflow is just as much a fault as one
+        // that lands anywhere else outside the program
+        Operand::Immediate(offset) => Some(location.saturating_add(*offset)),
+        Operand::Register(_) => None,
+    }
+}
+
+impl ControlFlow {
+    pub fn new(instructions: &[Instruction]) -> ControlFlow {
+        let mut graph = DiGraphMap::new();
+        let mut dynamic_jumps = Vec::new();
+        let mut exact = true;
+        let length = instructions.len() as isize;
+
+        graph.add_node(length);
+        for (idx, instruction) in instructions.iter().enumerate() {
+            let location = idx as isize;
+
+            graph.add_node(location);
+            match instruction.opcode.flow {
+                Flow::Next => {
+                    graph.add_edge(location, location + 1, ());
+                }
+                Flow::Jump(operand) => match jump_target(location, instruction, operand) {
+                    Some(target) => {
+                        graph.add_edge(location, target, ());
+                    }
+                    None => dynamic_jumps.push(location),
+                },
+                Flow::Branch(operand) => {
+                    exact = false;
+                    graph.add_edge(location, location + 1, ());
+                    match jump_target(location, instruction, operand) {
+                        Some(target) => {
+                            graph.add_edge(location, target, ());
+                        }
+                        None => dynamic_jumps.push(location),
+                    }
+                }
+            }
+        }
+
+        ControlFlow {
+            exact: exact && dynamic_jumps.is_empty(),
+            graph,
+            length,
+            dynamic_jumps,
+        }
+    }
+
+    fn is_instruction(&self, location: isize) -> bool {
+        location >= 0 && location < self.length
+    }
+
+    /// Every instruction that can be reached from the start of the program.
+    pub fn reachable(&self) -> BTreeSet<isize> {
+        let mut result = BTreeSet::new();
+        let mut dfs = Dfs::new(&self.graph, 0);
+
+        while let Some(location) = dfs.next(&self.graph) {
+            if self.is_instruction(location) {
+                result.insert(location);
+            }
+        }
+
+        result
+    }
+
+    /// Every instruction from which there is some path to the halting
+    /// location.
+    pub fn reaches_halt(&self) -> BTreeSet<isize> {
+        let reversed = Reversed(&self.graph);
+        let mut result = BTreeSet::new();
+        let mut dfs = Dfs::new(reversed, self.length);
+
+        while let Some(location) = dfs.next(reversed) {
+            if self.is_instruction(location) {
+                result.insert(location);
+            }
+        }
+
+        result
+    }
+
+    pub fn unreachable(&self) -> Vec<isize> {
+        let reachable = self.reachable();
+        (0..self.length)
+            .filter(|x| !reachable.contains(x))
+            .collect()
+    }
+
+    /// Instructions that can send control somewhere other than another
+    /// instruction or the halting location.
+    pub fn faults(&self) -> Vec<isize> {
+        (0..self.length)
+            .filter(|x| {
+                self.graph
+                    .neighbors(*x)
+                    .any(|y| !self.is_instruction(y) && y != self.length)
+            })
+            .collect()
+    }
+
+    /// Every loop in the program, as the sorted set of instructions in each
+    /// strongly connected component that can actually cycle.
+    pub fn loops(&self) -> Vec<Vec<isize>> {
+        let mut result: Vec<Vec<isize>> = tarjan_scc(&self.graph)
+            .into_iter()
+            .filter(|scc| scc.len() > 1 || self.graph.contains_edge(scc[0], scc[0]))
+            .map(|mut scc| {
+                scc.sort_unstable();
+                scc
+            })
+            .collect();
+
+        result.sort();
+        result
+    }
+
+    /// Finds a `jmp`/`nop` flip that makes the program halt, in linear time.
+    /// Flipping a reachable instruction so that it lands on one that already
+    /// reaches the halting location is enough: the new path can't run back
+    /// through the flipped instruction, or the original would have halted.
+    ///
+    /// That only holds when the graph is `exact`, as it is for day 8's
+    /// `jmp`/`nop`/`acc` programs, so this gives up on anything with a
+    /// branch or dynamic jump in it. It also takes it on trust that the
+    /// instructions that aren't jumps never fail.
+    pub fn repair(&self, instructions: &[Instruction]) -> Option<isize> {
+        if !self.exact {
+            return None;
+        }
+
+        let reaches_halt = self.reaches_halt();
+
+        for location in self.reachable() {
+            let instruction = &instructions[location as usize];
+            let flipped_target = if instruction.is("jmp") {
+                location + 1
+            } else if instruction.is("nop") {
+                match jump_target(location, instruction, 0) {
+                    Some(target) => target,
+                    None => continue,
+                }
+            } else {
+                continue;
+            };
+
+            if flipped_target == self.length || reaches_halt.contains(&flipped_target) {
+                return Some(location);
+            }
+        }
+
+        None
+    }
+}
+
+impl Machine {
+    pub fn control_flow(&self) -> ControlFlow {
+        ControlFlow::new(&self.instructions)
+    }
+}
+
+#[test]
+fn day8_analysis() {
+    use std::str::FromStr;
+
+    let contents = std::fs::read_to_string("inputs/day8_test.txt").unwrap();
+    let machine = Machine::from_str(&contents).unwrap();
+    let flow = machine.control_flow();
+
+    assert_eq!(vec![5, 8], flow.unreachable());
+    assert_eq!(vec![vec![1, 2, 3, 4, 6, 7]], flow.loops());
+    assert!(flow.faults().is_empty());
+    assert_eq!(
+        vec![8].into_iter().collect::<BTreeSet<_>>(),
+        flow.reaches_halt()
+    );
+
+    let fix = flow.repair(&machine.instructions).unwrap();
+    assert_eq!(7, fix);
+    assert_eq!(
+        (true, 8),
+        machine.flipped(fix).unwrap().terminates().unwrap()
+    );
+}
+
+#[test]
+fn faults_and_dynamic_jumps() {
+    use std::str::FromStr;
+
+    let machine = Machine::from_str("jnz a 7\njnz 1 b\njmp +0\nout 1\n").unwrap();
+    let flow = machine.control_flow();
+
+    assert_eq!(vec![0], flow.faults());
+    assert_eq!(vec![1], flow.dynamic_jumps);
+    assert_eq!(vec![vec![2]], flow.loops());
+    assert_eq!(vec![3], flow.unreachable());
+    assert!(!flow.exact);
+    assert_eq!(None, flow.repair(&machine.instructions));
+
+    let far = format!("nop +1\njmp {}\n", isize::MAX);
+    let machine = Machine::from_str(&far).unwrap();
+    let flow = machine.control_flow();
+    assert!(flow.exact);
+    assert_eq!(vec![1], flow.faults());
+}
@@ -0,0 +1,270 @@
+use crate::errors::AssemblyError;
+use crate::machine::{Flow, Instruction, Machine, Opcode, Operand};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt::Write;
+use std::str::FromStr;
+
+// Assembly source is the plain one-instruction-per-line format, plus:
+//
+//   ; comments, or # comments, to the end of the line
+//   name:            a label for the next instruction (or for the end of
+//                    the program, if nothing follows it)
+//   jmp name         in a jump offset's position, a label is turned into
+//                    the right relative offset
+//   .equ name 12     a named constant, usable anywhere an immediate is
+//   .reg a 5         start register a at 5
+//   .input 1 2 3     values for the `in` instruction to read
+//
+// A label in a jump offset's position wins over a register of the same name,
+// and so does a constant anywhere else.
+
+struct SourceLine<'a> {
+    line: usize,
+    opcode: &'a str,
+    operands: Vec<&'a str>,
+}
+
+fn is_label(s: &str) -> bool {
+    let mut chars = s.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_lowercase() || c == '_' => {}
+        _ => return false,
+    }
+    chars.all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+}
+
+fn strip_comment(line: &str) -> &str {
+    match line.find([';', '#']) {
+        None => line,
+        Some(idx) => &line[0..idx],
+    }
+}
+
+fn directive_value(
+    constants: &HashMap<String, isize>,
+    line: usize,
+    s: &str,
+) -> Result<isize, AssemblyError> {
+    match constants.get(s) {
+        Some(x) => Ok(*x),
+        None => isize::from_str(s).map_err(|_| AssemblyError::BadValue(line, s.to_string())),
+    }
+}
+
+pub fn assemble(source: &str) -> Result<Machine, AssemblyError> {
+    let lowered = source.to_ascii_lowercase();
+    let mut labels = HashMap::new();
+    let mut constants = HashMap::new();
+    let mut registers = BTreeMap::new();
+    let mut input = Vec::new();
+    let mut lines = Vec::new();
+
+    // first pass: find labels and directives, and count instructions
+    for (idx, raw_line) in lowered.lines().enumerate() {
+        let line = idx + 1;
+        let mut rest = strip_comment(raw_line).trim();
+
+        while let Some(colon) = rest.find(':') {
+            let label = rest[0..colon].trim();
+            if !is_label(label) {
+                return Err(AssemblyError::BadLabel(line, label.to_string()));
+            }
+            if labels
+                .insert(label.to_string(), lines.len() as isize)
+                .is_some()
+            {
+                return Err(AssemblyError::DuplicateLabel(line, label.to_string()));
+            }
+            rest = rest[colon + 1..].trim();
+        }
+
+        let mut items = rest.split_whitespace();
+        let opcode = match items.next() {
+            None => continue,
+            Some(x) => x,
+        };
+        let operands: Vec<&str> = items.collect();
+
+        match opcode {
+            ".equ" => match operands.as_slice() {
+                [name, value] if is_label(name) => {
+                    let value = directive_value(&constants, line, value)?;
+                    constants.insert(name.to_string(), value);
+                }
+                _ => return Err(AssemblyError::BadDirective(line, rest.to_string())),
+            },
+            ".reg" => match operands.as_slice() {
+                [name, value] if name.chars().all(|c| c.is_ascii_lowercase()) => {
+                    let value = directive_value(&constants, line, value)?;
+                    registers.insert(name.to_string(), value);
+                }
+                _ => return Err(AssemblyError::BadDirective(line, rest.to_string())),
+            },
+            ".input" => {
+                for value in operands {
+                    input.push(directive_value(&constants, line, value)?);
+                }
+            }
+            _ if opcode.starts_with('.') => {
+                return Err(AssemblyError::UnknownDirective(line, opcode.to_string()))
+            }
+            _ => lines.push(SourceLine {
+                line,
+                opcode,
+                operands,
+            }),
+        }
+    }
+
+    // second pass: resolve names and build the instructions
+    let mut instructions = Vec::with_capacity(lines.len());
+    for (location, source_line) in lines.iter().enumerate() {
+        let line = source_line.line;
+        let jump_operand = Opcode::lookup(source_line.opcode).and_then(|x| match x.flow {
+            Flow::Next => None,
+            Flow::Jump(idx) | Flow::Branch(idx) => Some(idx),
+        });
+        let mut operands = Vec::with_capacity(source_line.operands.len());
+
+        for (idx, operand) in source_line.operands.iter().enumerate() {
+            let resolved = match (labels.get(*operand), constants.get(*operand)) {
+                (Some(target), _) if jump_operand == Some(idx) => {
+                    Operand::Immediate(target - location as isize)
+                }
+                (_, Some(value)) => Operand::Immediate(*value),
+                _ => Operand::from_str(operand)
+                    .map_err(|e| AssemblyError::BadInstruction(line, e))?,
+            };
+            operands.push(resolved);
+        }
+
+        let instruction = Instruction::new(source_line.opcode, operands)
+            .map_err(|e| AssemblyError::BadInstruction(line, e))?;
+        instructions.push(instruction);
+    }
+
+    let mut machine = Machine::new(instructions);
+    machine.registers = registers;
+    machine.input = input.into_iter().collect();
+    Ok(machine)
+}
+
+fn jump_target(location: usize, instruction: &Instruction) -> Option<(usize, isize)> {
+    let operand = match instruction.opcode.flow {
+        Flow::Next => return None,
+        Flow::Jump(idx) | Flow::Branch(idx) => idx,
+    };
+
+    match instruction.operands[operand] {
+        Operand::Immediate(offset) => Some((operand, location as isize + offset)),
+        Operand::Register(_) => None,
+    }
+}
+
+fn label_name(target: isize) -> String {
+    format!("l{:04}", target)
+}
+
+/// Turns a machine back into assembly, with a label at every static jump
+/// target. Jumps that leave the program keep their numeric offsets.
+pub fn disassemble(machine: &Machine) -> String {
+    let length = machine.instructions.len() as isize;
+    let mut targets = BTreeSet::new();
+    let mut result = String::new();
+
+    for (location, instruction) in machine.instructions.iter().enumerate() {
+        if let Some((_, target)) = jump_target(location, instruction) {
+            if target >= 0 && target <= length {
+                targets.insert(target);
+            }
+        }
+    }
+
+    for (name, value) in machine.registers.iter() {
+        writeln!(result, ".reg {} {}", name, value).unwrap();
+    }
+    if !machine.input.is_empty() {
+        let values: Vec<String> = machine.input.iter().map(|x| x.to_string()).collect();
+        writeln!(result, ".input {}", values.join(" ")).unwrap();
+    }
+
+    for (location, instruction) in machine.instructions.iter().enumerate() {
+        if targets.contains(&(location as isize)) {
+            writeln!(result, "{}:", label_name(location as isize)).unwrap();
+        }
+
+        write!(
+            result,
+            "    {}",
+            instruction.opcode.name.to_ascii_uppercase()
+        )
+        .unwrap();
+        let jump = jump_target(location, instruction);
+        for (idx, operand) in instruction.operands.iter().enumerate() {
+            match jump {
+                Some((jump_idx, target)) if jump_idx == idx && targets.contains(&target) => {
+                    write!(result, " {}", label_name(target)).unwrap()
+                }
+                _ => write!(result, " {}", operand).unwrap(),
+            }
+        }
+        writeln!(result).unwrap();
+    }
+
+    if targets.contains(&length) {
+        writeln!(result, "{}:", label_name(length)).unwrap();
+    }
+
+    result
+}
+
+#[test]
+fn assemble_labels_and_directives() {
+    let source = "\
+        .equ start 3   ; the starting count\n\
+        .reg a start\n\
+        .input 7\n\
+        \n\
+        in b\n\
+        top:  add b 1  # bump it\n\
+              sub a 1\n\
+              jnz a top\n\
+              jmp end\n\
+              out 99\n\
+        end:\n";
+    let mut machine = assemble(source).unwrap();
+
+    assert_eq!(6, machine.instructions.len());
+    assert_eq!(Operand::Immediate(-2), machine.instructions[3].operands[1]);
+    assert_eq!(Operand::Immediate(2), machine.instructions[4].operands[0]);
+    machine.run().unwrap();
+    assert_eq!(10, machine.register("b"));
+    assert!(machine.output.is_empty());
+}
+
+#[test]
+fn assembly_errors() {
+    assert!(matches!(
+        assemble("a:\nnop +0\na: nop +0\n"),
+        Err(AssemblyError::DuplicateLabel(3, _))
+    ));
+    assert!(matches!(
+        assemble("nop +0\n.frob 3\n"),
+        Err(AssemblyError::UnknownDirective(2, _))
+    ));
+    assert!(matches!(
+        assemble("nop +0\ncpy 1 2\n"),
+        Err(AssemblyError::BadInstruction(2, _))
+    ));
+}
+
+#[test]
+fn disassembly_round_trip() {
+    let contents = std::fs::read_to_string("inputs/day8_test.txt").unwrap();
+    let machine = Machine::from_str(&contents).unwrap();
+    let text = disassemble(&machine);
+
+    assert!(text.contains("l0006:\n    ACC +1\n    JMP l0003\n"));
+    let reassembled = assemble(&text).unwrap();
+    assert_eq!(machine.instructions, reassembled.instructions);
+}
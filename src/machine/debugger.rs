@@ -0,0 +1,537 @@
+use crate::errors::{DebugCommandParseError, ExecutionError};
+use crate::machine::{Machine, Operand, OperandKind, ACCUMULATOR};
+use std::collections::{BTreeSet, HashSet, VecDeque};
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+impl Comparison {
+    fn holds(&self, left: isize, right: isize) -> bool {
+        match self {
+            Comparison::Equal => left == right,
+            Comparison::NotEqual => left != right,
+            Comparison::Less => left < right,
+            Comparison::LessOrEqual => left <= right,
+            Comparison::Greater => left > right,
+            Comparison::GreaterOrEqual => left >= right,
+        }
+    }
+}
+
+impl fmt::Display for Comparison {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Comparison::Equal => write!(f, "=="),
+            Comparison::NotEqual => write!(f, "!="),
+            Comparison::Less => write!(f, "<"),
+            Comparison::LessOrEqual => write!(f, "<="),
+            Comparison::Greater => write!(f, ">"),
+            Comparison::GreaterOrEqual => write!(f, ">="),
+        }
+    }
+}
+
+/// A watch fires either whenever its register changes, or when the
+/// comparison goes from false to true.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Watch {
+    pub register: String,
+    pub condition: Option<(Comparison, isize)>,
+}
+
+impl Watch {
+    fn triggered(&self, old: isize, new: isize) -> bool {
+        match self.condition {
+            None => old != new,
+            Some((cmp, value)) => !cmp.holds(old, value) && cmp.holds(new, value),
+        }
+    }
+}
+
+impl FromStr for Watch {
+    type Err = DebugCommandParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut items = s.split_whitespace();
+        let first = items.next().unwrap_or(ACCUMULATOR);
+
+        // "watch > 5" is shorthand for "watch acc > 5"
+        let (register, cmp) = if first.chars().all(|c| c.is_ascii_lowercase()) {
+            (first.to_string(), items.next())
+        } else {
+            (ACCUMULATOR.to_string(), Some(first))
+        };
+
+        let condition = match cmp {
+            None => None,
+            Some(cmp) => {
+                let comparison = match cmp {
+                    "==" => Comparison::Equal,
+                    "!=" => Comparison::NotEqual,
+                    "<" => Comparison::Less,
+                    "<=" => Comparison::LessOrEqual,
+                    ">" => Comparison::Greater,
+                    ">=" => Comparison::GreaterOrEqual,
+                    _ => return Err(DebugCommandParseError::BadWatch(s.to_string())),
+                };
+                let value = items
+                    .next()
+                    .ok_or_else(|| DebugCommandParseError::BadWatch(s.to_string()))?;
+                Some((comparison, isize::from_str(value)?))
+            }
+        };
+
+        if items.next().is_some() {
+            return Err(DebugCommandParseError::BadWatch(s.to_string()));
+        }
+
+        Ok(Watch {
+            register,
+            condition,
+        })
+    }
+}
+
+impl fmt::Display for Watch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.condition {
+            None => write!(f, "{} changes", self.register),
+            Some((cmp, value)) => write!(f, "{} {} {}", self.register, cmp, value),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum DebugCommand {
+    Step,
+    StepOver,
+    Continue,
+    RunUntilLoop,
+    ReverseStep,
+    Break(isize),
+    Delete(isize),
+    Watch(Watch),
+    Unwatch(usize),
+    Print,
+    Registers,
+    Quit,
+}
+
+impl FromStr for DebugCommand {
+    type Err = DebugCommandParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let trimmed = s.trim();
+        let (command, rest) = match trimmed.find(' ') {
+            None => (trimmed, ""),
+            Some(idx) => (&trimmed[0..idx], trimmed[idx..].trim()),
+        };
+
+        match command {
+            "s" | "step" => Ok(DebugCommand::Step),
+            "n" | "next" => Ok(DebugCommand::StepOver),
+            "c" | "continue" => Ok(DebugCommand::Continue),
+            "l" | "loop" => Ok(DebugCommand::RunUntilLoop),
+            "r" | "back" => Ok(DebugCommand::ReverseStep),
+            "b" | "break" => Ok(DebugCommand::Break(isize::from_str(rest)?)),
+            "d" | "delete" => Ok(DebugCommand::Delete(isize::from_str(rest)?)),
+            "w" | "watch" => Ok(DebugCommand::Watch(Watch::from_str(rest)?)),
+            "u" | "unwatch" => Ok(DebugCommand::Unwatch(usize::from_str(rest)?)),
+            "p" | "print" => Ok(DebugCommand::Print),
+            "i" | "registers" => Ok(DebugCommand::Registers),
+            "q" | "quit" => Ok(DebugCommand::Quit),
+            "" => Err(DebugCommandParseError::EmptyCommand),
+            _ => Err(DebugCommandParseError::UnknownCommand(command.to_string())),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum StopReason {
+    Stepped,
+    Breakpoint(isize),
+    WatchTriggered(usize),
+    Halted,
+    LoopDetected(isize),
+    NoHistory,
+    StepLimit(usize),
+}
+
+impl fmt::Display for StopReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StopReason::Stepped => write!(f, "stepped"),
+            StopReason::Breakpoint(x) => write!(f, "hit breakpoint at {:04}", x),
+            StopReason::WatchTriggered(x) => write!(f, "watch #{} triggered", x),
+            StopReason::Halted => write!(f, "program halted"),
+            StopReason::LoopDetected(x) => write!(f, "about to loop back to {:04}", x),
+            StopReason::NoHistory => write!(f, "already at the start of history"),
+            StopReason::StepLimit(x) => write!(f, "gave up after {} steps", x),
+        }
+    }
+}
+
+/// How many steps `reverse_step` can undo by default.
+pub const DEFAULT_HISTORY_LIMIT: usize = 100_000;
+/// How many steps `continue` and `next` run before giving up by default.
+pub const DEFAULT_STEP_LIMIT: usize = 10_000_000;
+
+/// Just enough to undo one step: the registers it could have written, as
+/// they were (`None` if they didn't exist yet), and any input it read.
+struct Undo {
+    location: isize,
+    registers: Vec<(String, Option<isize>)>,
+    input: Option<isize>,
+    output_length: usize,
+}
+
+pub struct Debugger {
+    pub machine: Machine,
+    pub breakpoints: BTreeSet<isize>,
+    pub watches: Vec<Watch>,
+    pub history_limit: usize,
+    pub step_limit: usize,
+    history: VecDeque<Undo>,
+}
+
+impl Debugger {
+    pub fn new(machine: Machine) -> Debugger {
+        Debugger {
+            machine,
+            breakpoints: BTreeSet::new(),
+            watches: Vec::new(),
+            history_limit: DEFAULT_HISTORY_LIMIT,
+            step_limit: DEFAULT_STEP_LIMIT,
+            history: VecDeque::new(),
+        }
+    }
+
+    pub fn history_len(&self) -> usize {
+        self.history.len()
+    }
+
+    fn step_once(&mut self) -> Result<Option<StopReason>, ExecutionError> {
+        if self.machine.halted() {
+            return Ok(Some(StopReason::Halted));
+        }
+
+        let undo = self.undo_record();
+        let input_length = self.machine.input.len();
+        self.machine.step()?;
+        let undo = Undo {
+            input: undo
+                .input
+                .filter(|_| self.machine.input.len() < input_length),
+            ..undo
+        };
+
+        let triggered = self.watches.iter().position(|watch| {
+            let old = undo
+                .registers
+                .iter()
+                .find(|(name, _)| *name == watch.register)
+                .map_or_else(
+                    || self.machine.register(&watch.register),
+                    |x| x.1.unwrap_or(0),
+                );
+            watch.triggered(old, self.machine.register(&watch.register))
+        });
+
+        self.history.push_back(undo);
+        while self.history.len() > self.history_limit {
+            self.history.pop_front();
+        }
+
+        if let Some(idx) = triggered {
+            return Ok(Some(StopReason::WatchTriggered(idx)));
+        }
+
+        if self.machine.halted() {
+            return Ok(Some(StopReason::Halted));
+        }
+
+        if self.breakpoints.contains(&self.machine.location) {
+            return Ok(Some(StopReason::Breakpoint(self.machine.location)));
+        }
+
+        Ok(None)
+    }
+
+    /// What it would take to undo the current instruction, before it runs.
+    fn undo_record(&self) -> Undo {
+        let mut registers = vec![(
+            ACCUMULATOR.to_string(),
+            self.machine.registers.get(ACCUMULATOR).copied(),
+        )];
+
+        if let Some(instruction) = self
+            .machine
+            .instructions
+            .get(self.machine.location as usize)
+        {
+            let written = instruction
+                .opcode
+                .operands
+                .iter()
+                .zip(instruction.operands.iter());
+            for (kind, operand) in written {
+                if let (OperandKind::Register, Operand::Register(name)) = (kind, operand) {
+                    registers.push((name.clone(), self.machine.registers.get(name).copied()));
+                }
+            }
+        }
+
+        Undo {
+            location: self.machine.location,
+            registers,
+            input: self.machine.input.front().copied(),
+            output_length: self.machine.output.len(),
+        }
+    }
+
+    pub fn step(&mut self) -> Result<StopReason, ExecutionError> {
+        Ok(self.step_once()?.unwrap_or(StopReason::Stepped))
+    }
+
+    /// Runs until control falls through to the instruction after the current
+    /// one, so a backwards loop that starts here runs to completion. Gives
+    /// up after `step_limit` steps, since it may never get there.
+    pub fn step_over(&mut self) -> Result<StopReason, ExecutionError> {
+        let target = self.machine.location + 1;
+
+        for _ in 0..self.step_limit {
+            if let Some(reason) = self.step_once()? {
+                return Ok(reason);
+            }
+
+            if self.machine.location == target {
+                return Ok(StopReason::Stepped);
+            }
+        }
+
+        Ok(StopReason::StepLimit(self.step_limit))
+    }
+
+    /// Runs until something stops it, or for `step_limit` steps, whichever
+    /// comes first.
+    pub fn continue_running(&mut self) -> Result<StopReason, ExecutionError> {
+        for _ in 0..self.step_limit {
+            if let Some(reason) = self.step_once()? {
+                return Ok(reason);
+            }
+        }
+
+        Ok(StopReason::StepLimit(self.step_limit))
+    }
+
+    /// Like `Machine::terminates`, stops right before an instruction would
+    /// be run for the second time since this command started.
+    pub fn run_until_loop(&mut self) -> Result<StopReason, ExecutionError> {
+        let mut visited_locations = HashSet::new();
+
+        loop {
+            visited_locations.insert(self.machine.location);
+
+            if let Some(reason) = self.step_once()? {
+                return Ok(reason);
+            }
+
+            if visited_locations.contains(&self.machine.location) {
+                return Ok(StopReason::LoopDetected(self.machine.location));
+            }
+        }
+    }
+
+    pub fn reverse_step(&mut self) -> StopReason {
+        match self.history.pop_back() {
+            None => StopReason::NoHistory,
+            Some(undo) => {
+                self.machine.location = undo.location;
+                for (name, value) in undo.registers.into_iter() {
+                    match value {
+                        Some(value) => self.machine.registers.insert(name, value),
+                        None => self.machine.registers.remove(&name),
+                    };
+                }
+                if let Some(value) = undo.input {
+                    self.machine.input.push_front(value);
+                }
+                self.machine.output.truncate(undo.output_length);
+                StopReason::Stepped
+            }
+        }
+    }
+
+    pub fn print_registers(&self) {
+        println!("pc = {:04}", self.machine.location);
+        if !self.machine.registers.contains_key(ACCUMULATOR) {
+            println!("{} = 0", ACCUMULATOR);
+        }
+        for (name, value) in self.machine.registers.iter() {
+            println!("{} = {}", name, value);
+        }
+        for (idx, watch) in self.watches.iter().enumerate() {
+            println!("watch #{}: {}", idx, watch);
+        }
+    }
+
+    pub fn print_current(&self) {
+        match self
+            .machine
+            .instructions
+            .get(self.machine.location as usize)
+        {
+            Some(instr) if self.machine.location >= 0 => {
+                println!("--> {:04}: {}", self.machine.location, instr)
+            }
+            _ => println!("--> {:04}: <end of program>", self.machine.location),
+        }
+    }
+
+    /// Runs one command, returning `false` once the user asks to quit.
+    pub fn execute(&mut self, command: DebugCommand) -> Result<bool, ExecutionError> {
+        let reason = match command {
+            DebugCommand::Step => self.step()?,
+            DebugCommand::StepOver => self.step_over()?,
+            DebugCommand::Continue => self.continue_running()?,
+            DebugCommand::RunUntilLoop => self.run_until_loop()?,
+            DebugCommand::ReverseStep => self.reverse_step(),
+            DebugCommand::Break(x) => {
+                self.breakpoints.insert(x);
+                println!("Breakpoint set at {:04}", x);
+                return Ok(true);
+            }
+            DebugCommand::Delete(x) => {
+                if !self.breakpoints.remove(&x) {
+                    println!("No breakpoint at {:04}", x);
+                }
+                return Ok(true);
+            }
+            DebugCommand::Watch(watch) => {
+                println!("Watch #{}: {}", self.watches.len(), watch);
+                self.watches.push(watch);
+                return Ok(true);
+            }
+            DebugCommand::Unwatch(x) => {
+                if x < self.watches.len() {
+                    self.watches.remove(x);
+                } else {
+                    println!("No watch #{}", x);
+                }
+                return Ok(true);
+            }
+            DebugCommand::Print => {
+                self.machine.pretty_print();
+                return Ok(true);
+            }
+            DebugCommand::Registers => {
+                self.print_registers();
+                return Ok(true);
+            }
+            DebugCommand::Quit => return Ok(false),
+        };
+
+        if reason != StopReason::Stepped {
+            println!("Stopped: {}", reason);
+        }
+        self.print_current();
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+fn test_debugger() -> Debugger {
+    let contents = std::fs::read_to_string("inputs/day8_test.txt").unwrap();
+    Debugger::new(Machine::from_str(&contents).unwrap())
+}
+
+#[test]
+fn breakpoints_and_loops() {
+    let mut debugger = test_debugger();
+    debugger.breakpoints.insert(4);
+    assert_eq!(
+        StopReason::Breakpoint(4),
+        debugger.continue_running().unwrap()
+    );
+    assert_eq!(5, debugger.machine.accumulator());
+
+    let mut debugger = test_debugger();
+    assert_eq!(
+        StopReason::LoopDetected(1),
+        debugger.run_until_loop().unwrap()
+    );
+    assert_eq!(5, debugger.machine.accumulator());
+}
+
+#[test]
+fn watches_and_reverse() {
+    let mut debugger = test_debugger();
+    debugger.watches.push(Watch::from_str("> 2").unwrap());
+    assert_eq!(
+        StopReason::WatchTriggered(0),
+        debugger.continue_running().unwrap()
+    );
+    assert_eq!(5, debugger.machine.accumulator());
+    assert_eq!(6, debugger.history_len());
+    assert_eq!(StopReason::Stepped, debugger.reverse_step());
+    assert_eq!(2, debugger.machine.accumulator());
+    assert_eq!(3, debugger.machine.location);
+    while debugger.history_len() > 0 {
+        debugger.reverse_step();
+    }
+    assert_eq!(StopReason::NoHistory, debugger.reverse_step());
+    assert_eq!(0, debugger.machine.location);
+    assert_eq!(0, debugger.machine.accumulator());
+}
+
+#[test]
+fn limits() {
+    // day 8's program loops forever without a breakpoint
+    let mut debugger = test_debugger();
+    debugger.step_limit = 1000;
+    debugger.history_limit = 10;
+    assert_eq!(
+        StopReason::StepLimit(1000),
+        debugger.continue_running().unwrap()
+    );
+    assert_eq!(10, debugger.history_len());
+    // jmp -4 at 7 never falls through to 8
+    while debugger.machine.location != 7 {
+        debugger.step().unwrap();
+    }
+    assert_eq!(StopReason::StepLimit(1000), debugger.step_over().unwrap());
+
+    let program = "in a\ncpy 2 b\nmul a b\nout a\nin a\n";
+    let mut debugger = Debugger::new(Machine::from_str(program).unwrap());
+    debugger.machine.input.extend(vec![7, 9]);
+    debugger.continue_running().unwrap();
+    assert_eq!(vec![14], debugger.machine.output);
+    while debugger.history_len() > 0 {
+        debugger.reverse_step();
+    }
+    assert_eq!(vec![7, 9], Vec::from(debugger.machine.input.clone()));
+    assert!(debugger.machine.registers.is_empty());
+    assert!(debugger.machine.output.is_empty());
+}
+
+#[test]
+fn command_parsing() {
+    assert_eq!(Ok(DebugCommand::Break(12)), DebugCommand::from_str("b 12"));
+    assert_eq!(
+        Ok(DebugCommand::Watch(Watch {
+            register: "acc".to_string(),
+            condition: Some((Comparison::GreaterOrEqual, -3)),
+        })),
+        DebugCommand::from_str("watch acc >= -3")
+    );
+    assert!(DebugCommand::from_str("frobnicate").is_err());
+}
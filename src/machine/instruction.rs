@@ -0,0 +1,124 @@
+use crate::errors::InstructionParseError;
+use crate::machine::opcodes::{Opcode, OperandKind};
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Operand {
+    Immediate(isize),
+    Register(String),
+}
+
+impl FromStr for Operand {
+    type Err = InstructionParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if !s.is_empty() && s.chars().all(|c| c.is_ascii_lowercase()) {
+            return Ok(Operand::Register(s.to_string()));
+        }
+
+        Ok(Operand::Immediate(isize::from_str(s)?))
+    }
+}
+
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Operand::Immediate(x) => write!(f, "{:+}", x),
+            Operand::Register(r) => write!(f, "{}", r),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Instruction {
+    pub opcode: &'static Opcode,
+    pub operands: Vec<Operand>,
+}
+
+impl PartialEq for Instruction {
+    fn eq(&self, other: &Instruction) -> bool {
+        self.opcode.name == other.opcode.name && self.operands == other.operands
+    }
+}
+
+impl Instruction {
+    pub fn new(name: &str, operands: Vec<Operand>) -> Result<Instruction, InstructionParseError> {
+        let opcode = Opcode::lookup(name)
+            .ok_or_else(|| InstructionParseError::UnknownOpcode(name.to_string()))?;
+
+        if operands.is_empty() && !opcode.operands.is_empty() {
+            return Err(InstructionParseError::MissingOperand(name.to_string()));
+        }
+
+        if operands.len() != opcode.operands.len() {
+            return Err(InstructionParseError::WrongOperandCount(
+                name.to_string(),
+                opcode.operands.len(),
+                operands.len(),
+            ));
+        }
+
+        for (kind, operand) in opcode.operands.iter().zip(operands.iter()) {
+            if let (OperandKind::Register, Operand::Immediate(_)) = (kind, operand) {
+                return Err(InstructionParseError::ExpectedRegister(
+                    name.to_string(),
+                    operand.to_string(),
+                ));
+            }
+        }
+
+        Ok(Instruction { opcode, operands })
+    }
+
+    pub fn is(&self, name: &str) -> bool {
+        self.opcode.name == name
+    }
+
+    /// Returns the same instruction with a different opcode, as long as the
+    /// new opcode takes the same kinds of operands.
+    pub fn with_opcode(&self, name: &str) -> Option<Instruction> {
+        let opcode = Opcode::lookup(name)?;
+
+        if opcode.operands != self.opcode.operands {
+            return None;
+        }
+
+        Some(Instruction {
+            opcode,
+            operands: self.operands.clone(),
+        })
+    }
+}
+
+impl FromStr for Instruction {
+    type Err = InstructionParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut lowered = s.to_string();
+
+        lowered.make_ascii_lowercase();
+
+        let mut items = lowered.split_whitespace();
+        let instruction = items
+            .next()
+            .ok_or(InstructionParseError::EmptyInstruction)?;
+        let mut operands = Vec::new();
+
+        for operand in items {
+            operands.push(Operand::from_str(operand)?);
+        }
+
+        Instruction::new(instruction, operands)
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.opcode.name.to_ascii_uppercase())?;
+        for operand in self.operands.iter() {
+            write!(f, " {}", operand)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,262 @@
+use crate::errors::ExecutionError;
+use crate::machine::abstract_interpreter::{AbstractOutcome, AbstractState, Interval};
+use crate::machine::instruction::Operand;
+use crate::machine::Machine;
+use std::cmp::max;
+use std::fmt;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OperandKind {
+    /// Either an immediate or a register, read but never written.
+    Value,
+    /// A register that the instruction writes to.
+    Register,
+}
+
+/// How an instruction can move the instruction pointer, for analyses that
+/// don't run the program. Jump offsets name the operand holding them.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Flow {
+    Next,
+    Jump(usize),
+    Branch(usize),
+}
+
+/// Handlers return how far to move the instruction pointer.
+type Handler = fn(&mut Machine, &[Operand]) -> Result<isize, ExecutionError>;
+
+/// The same thing over intervals, for `Machine::abstract_run`.
+type AbstractHandler = fn(&mut AbstractState, &[Operand]) -> AbstractOutcome;
+
+pub struct Opcode {
+    pub name: &'static str,
+    pub operands: &'static [OperandKind],
+    pub flow: Flow,
+    execute: Handler,
+    transfer: AbstractHandler,
+}
+
+impl fmt::Debug for Opcode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Opcode({})", self.name)
+    }
+}
+
+use OperandKind::{Register, Value};
+
+// To add an instruction, add an entry here; `Machine::step` and
+// `Machine::abstract_run` just look the handlers up.
+static OPCODES: &[Opcode] = &[
+    Opcode {
+        name: "nop",
+        operands: &[Value],
+        flow: Flow::Next,
+        execute: |_, _| Ok(1),
+        transfer: |_, _| AbstractOutcome::next(),
+    },
+    Opcode {
+        name: "acc",
+        operands: &[Value],
+        flow: Flow::Next,
+        execute: |m, args| {
+            let value = m
+                .accumulator()
+                .checked_add(m.value(&args[0]))
+                .ok_or(ExecutionError::Overflow(m.location))?;
+            *m.register_mut("acc") = value;
+            Ok(1)
+        },
+        transfer: |s, args| {
+            let value = s.register("acc").add(&s.value(&args[0]));
+            s.registers.insert("acc".to_string(), value);
+            AbstractOutcome {
+                may_fault: !value.is_bounded(),
+                ..AbstractOutcome::next()
+            }
+        },
+    },
+    Opcode {
+        name: "jmp",
+        operands: &[Value],
+        flow: Flow::Jump(0),
+        execute: |m, args| Ok(m.value(&args[0])),
+        transfer: |_, _| AbstractOutcome::jump(),
+    },
+    Opcode {
+        name: "jnz",
+        operands: &[Value, Value],
+        flow: Flow::Branch(1),
+        execute: |m, args| {
+            if m.value(&args[0]) != 0 {
+                Ok(m.value(&args[1]))
+            } else {
+                Ok(1)
+            }
+        },
+        transfer: |s, args| {
+            let test = s.value(&args[0]);
+            AbstractOutcome::branch(test.may_be_nonzero(), test.contains(0))
+        },
+    },
+    Opcode {
+        name: "jgz",
+        operands: &[Value, Value],
+        flow: Flow::Branch(1),
+        execute: |m, args| {
+            if m.value(&args[0]) > 0 {
+                Ok(m.value(&args[1]))
+            } else {
+                Ok(1)
+            }
+        },
+        transfer: |s, args| {
+            let test = s.value(&args[0]);
+            AbstractOutcome::branch(test.may_be_positive(), test.may_be_nonpositive())
+        },
+    },
+    Opcode {
+        name: "cpy",
+        operands: &[Value, Register],
+        flow: Flow::Next,
+        execute: |m, args| {
+            let value = m.value(&args[0]);
+            m.set(&args[1], value);
+            Ok(1)
+        },
+        transfer: |s, args| {
+            let value = s.value(&args[0]);
+            s.set(&args[1], value);
+            AbstractOutcome::next()
+        },
+    },
+    Opcode {
+        name: "add",
+        operands: &[Register, Value],
+        flow: Flow::Next,
+        execute: |m, args| arithmetic(m, args, isize::checked_add),
+        transfer: |s, args| abstract_arithmetic(s, args, Interval::add),
+    },
+    Opcode {
+        name: "sub",
+        operands: &[Register, Value],
+        flow: Flow::Next,
+        execute: |m, args| arithmetic(m, args, isize::checked_sub),
+        transfer: |s, args| abstract_arithmetic(s, args, Interval::sub),
+    },
+    Opcode {
+        name: "mul",
+        operands: &[Register, Value],
+        flow: Flow::Next,
+        execute: |m, args| arithmetic(m, args, isize::checked_mul),
+        transfer: |s, args| abstract_arithmetic(s, args, Interval::mul),
+    },
+    Opcode {
+        name: "mod",
+        operands: &[Register, Value],
+        flow: Flow::Next,
+        execute: |m, args| {
+            if m.value(&args[1]) == 0 {
+                return Err(ExecutionError::DivisionByZero(m.location));
+            }
+            arithmetic(m, args, isize::checked_rem_euclid)
+        },
+        transfer: |s, args| {
+            // the only remainder that overflows is isize::MIN by -1
+            let (left, right) = (s.value(&args[0]), s.value(&args[1]));
+            let may_fault = right.contains(0) || (left.contains(isize::MIN) && right.contains(-1));
+            let mut outcome = abstract_arithmetic(s, args, Interval::rem_euclid);
+            outcome.may_fault = may_fault;
+            outcome
+        },
+    },
+    Opcode {
+        name: "in",
+        operands: &[Register],
+        flow: Flow::Next,
+        execute: |m, args| {
+            let value = m
+                .input
+                .pop_front()
+                .ok_or(ExecutionError::InputExhausted(m.location))?;
+            m.set(&args[0], value);
+            Ok(1)
+        },
+        transfer: |s, args| {
+            let value = s.input;
+            s.set(&args[0], value);
+            // if this runs at all, there was at least one value to read
+            let left = s.input_left;
+            let may_fault = left.low.is_none_or(|x| x <= 0);
+            s.input_left = Interval {
+                low: left.low.map(|x| max(x - 1, 0)),
+                high: left.high.map(|x| max(x - 1, 0)),
+            };
+            AbstractOutcome {
+                may_fault,
+                ..AbstractOutcome::next()
+            }
+        },
+    },
+    Opcode {
+        name: "out",
+        operands: &[Value],
+        flow: Flow::Next,
+        execute: |m, args| {
+            let value = m.value(&args[0]);
+            m.output.push(value);
+            Ok(1)
+        },
+        transfer: |_, _| AbstractOutcome::next(),
+    },
+];
+
+/// Runs `f` on both operands, storing the result in the first; `f` gives
+/// back `None` when the result doesn't fit.
+fn arithmetic(
+    m: &mut Machine,
+    args: &[Operand],
+    f: fn(isize, isize) -> Option<isize>,
+) -> Result<isize, ExecutionError> {
+    let left = m.value(&args[0]);
+    let right = m.value(&args[1]);
+    let result = f(left, right).ok_or(ExecutionError::Overflow(m.location))?;
+    m.set(&args[0], result);
+    Ok(1)
+}
+
+fn abstract_arithmetic(
+    s: &mut AbstractState,
+    args: &[Operand],
+    f: fn(&Interval, &Interval) -> Interval,
+) -> AbstractOutcome {
+    // interval arithmetic drops a bound when it overflows, so an unbounded
+    // result means this might not fit in an isize
+    let result = f(&s.value(&args[0]), &s.value(&args[1]));
+    s.set(&args[0], result);
+    AbstractOutcome {
+        may_fault: !result.is_bounded(),
+        ..AbstractOutcome::next()
+    }
+}
+
+impl Opcode {
+    pub fn lookup(name: &str) -> Option<&'static Opcode> {
+        OPCODES.iter().find(|x| x.name == name)
+    }
+
+    pub fn all() -> &'static [Opcode] {
+        OPCODES
+    }
+
+    pub fn execute(
+        &self,
+        machine: &mut Machine,
+        operands: &[Operand],
+    ) -> Result<isize, ExecutionError> {
+        (self.execute)(machine, operands)
+    }
+
+    pub fn transfer(&self, state: &mut AbstractState, operands: &[Operand]) -> AbstractOutcome {
+        (self.transfer)(state, operands)
+    }
+}
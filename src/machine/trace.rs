@@ -0,0 +1,177 @@
+use crate::errors::{TraceError, TraceParseError};
+use crate::machine::{Instruction, Machine};
+use nom::bytes::complete::{tag, take_until};
+use nom::character::complete::{digit1, one_of};
+use nom::combinator::{opt, recognize};
+use nom::sequence::pair;
+use std::fmt;
+use std::io::{BufRead, Write};
+use std::str::FromStr;
+
+/// One executed instruction. `accumulator` is the value after the
+/// instruction ran.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TraceEntry {
+    pub step: usize,
+    pub location: isize,
+    pub instruction: Instruction,
+    pub accumulator: isize,
+}
+
+// Traces are JSON lines, but always with exactly this shape, so we don't
+// need a real JSON parser to read them back.
+impl fmt::Display for TraceEntry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{{\"step\":{},\"pc\":{},\"instruction\":\"{}\",\"acc\":{}}}",
+            self.step, self.location, self.instruction, self.accumulator
+        )
+    }
+}
+
+fn signed(input: &str) -> nom::IResult<&str, &str> {
+    recognize(pair(opt(one_of("+-")), digit1))(input)
+}
+
+impl FromStr for TraceEntry {
+    type Err = TraceParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (rest0, _) = tag("{\"step\":")(s)?;
+        let (rest1, step) = digit1(rest0)?;
+        let (rest2, _) = tag(",\"pc\":")(rest1)?;
+        let (rest3, location) = signed(rest2)?;
+        let (rest4, _) = tag(",\"instruction\":\"")(rest3)?;
+        let (rest5, instruction) = take_until("\"")(rest4)?;
+        let (rest6, _) = tag("\",\"acc\":")(rest5)?;
+        let (rest7, accumulator) = signed(rest6)?;
+        let (rest8, _) = tag("}")(rest7)?;
+
+        if !rest8.trim().is_empty() {
+            return Err(TraceParseError::TrailingGarbage(rest8.to_string()));
+        }
+
+        Ok(TraceEntry {
+            step: usize::from_str(step)?,
+            location: isize::from_str(location)?,
+            instruction: Instruction::from_str(instruction)?,
+            accumulator: isize::from_str(accumulator)?,
+        })
+    }
+}
+
+pub fn read_trace<R: BufRead>(reader: R) -> Result<Vec<TraceEntry>, TraceError> {
+    let mut result = Vec::new();
+
+    for (idx, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let entry = TraceEntry::from_str(&line).map_err(|e| TraceError::BadLine(idx + 1, e))?;
+        result.push(entry);
+    }
+
+    Ok(result)
+}
+
+impl Machine {
+    /// Exactly like `terminates`, but writes every executed instruction to
+    /// `out` as it goes.
+    pub fn trace<W: Write>(&mut self, out: &mut W) -> Result<(bool, isize), TraceError> {
+        let mut step = 0;
+
+        self.run_observed(|location, machine| {
+            let entry = TraceEntry {
+                step,
+                location,
+                instruction: machine.instructions[location as usize].clone(),
+                accumulator: machine.accumulator(),
+            };
+            step += 1;
+            writeln!(out, "{}", entry).map_err(TraceError::from)
+        })
+    }
+}
+
+/// Where two traces stop agreeing. One side is `None` if that trace ended
+/// first.
+#[derive(Debug, PartialEq)]
+pub struct Divergence<'a> {
+    pub step: usize,
+    pub left: Option<&'a TraceEntry>,
+    pub right: Option<&'a TraceEntry>,
+}
+
+/// Finds the first step at which two traces differ, ignoring the step
+/// counters themselves. Returns `None` if they're identical.
+pub fn diverge<'a>(left: &'a [TraceEntry], right: &'a [TraceEntry]) -> Option<Divergence<'a>> {
+    let mut step = 0;
+
+    loop {
+        let l = left.get(step);
+        let r = right.get(step);
+
+        match (l, r) {
+            (None, None) => return None,
+            (Some(a), Some(b))
+                if a.location == b.location
+                    && a.instruction == b.instruction
+                    && a.accumulator == b.accumulator => {}
+            _ => {
+                return Some(Divergence {
+                    step,
+                    left: l,
+                    right: r,
+                })
+            }
+        }
+
+        step += 1;
+    }
+}
+
+#[test]
+fn trace_round_trip() {
+    let contents = std::fs::read_to_string("inputs/day8_test.txt").unwrap();
+    let machine = Machine::from_str(&contents).unwrap();
+    let mut buffer = Vec::new();
+
+    assert_eq!((false, 5), machine.clone().trace(&mut buffer).unwrap());
+    let text = String::from_utf8(buffer.clone()).unwrap();
+    assert_eq!(
+        Some("{\"step\":2,\"pc\":2,\"instruction\":\"JMP +4\",\"acc\":1}"),
+        text.lines().nth(2)
+    );
+
+    let entries = read_trace(&buffer[..]).unwrap();
+    assert_eq!(7, entries.len());
+    assert_eq!(5, entries[6].accumulator);
+    assert_eq!(4, entries[6].location);
+    assert!(matches!(
+        read_trace("{\"step\":0,\"pc\":0}\n".as_bytes()),
+        Err(TraceError::BadLine(1, _))
+    ));
+}
+
+#[test]
+fn trace_divergence() {
+    let contents = std::fs::read_to_string("inputs/day8_test.txt").unwrap();
+    let machine = Machine::from_str(&contents).unwrap();
+    let mut original = Vec::new();
+    let mut fixed = Vec::new();
+
+    machine.clone().trace(&mut original).unwrap();
+    machine.flipped(7).unwrap().trace(&mut fixed).unwrap();
+
+    let original = read_trace(&original[..]).unwrap();
+    let fixed = read_trace(&fixed[..]).unwrap();
+    let divergence = diverge(&original, &fixed).unwrap();
+
+    assert_eq!(4, divergence.step);
+    assert_eq!(7, divergence.left.unwrap().location);
+    assert!(divergence.left.unwrap().instruction.is("jmp"));
+    assert!(divergence.right.unwrap().instruction.is("nop"));
+    assert_eq!(None, diverge(&original, &original));
+}
@@ -8,7 +8,7 @@ pub struct Map<A: Clone> {
     data: Vec<Vec<A>>,
 }
 
-impl<'a, E, X> TryFrom<&'a str> for Map<X>
+impl<E, X> TryFrom<&str> for Map<X>
 where
     X: Clone + TryFrom<char, Error = E>,
     E: From<MapParseError>,
@@ -125,7 +125,7 @@ impl<X: Clone> Map<X> {
         Ok(results)
     }
 
-    pub fn locations(&self) -> MapLocations<X> {
+    pub fn locations(&self) -> MapLocations<'_, X> {
         MapLocations {
             underlying: self,
             x: 0,
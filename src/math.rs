@@ -1,5 +1,4 @@
 mod ast;
-#[allow(clippy::all)]
 lalrpop_util::lalrpop_mod!(
     #[allow(clippy::all)]
     parse,